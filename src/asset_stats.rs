@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, HashSet};
+
+use bevy::{asset::UntypedAssetId, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{line::LineMaterial, ui_state::UiState};
+
+pub struct AssetStatsPlugin;
+
+impl Plugin for AssetStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LiveAssets>()
+            .add_systems(
+                Update,
+                (
+                    track_live_assets_system::<Image>,
+                    track_live_assets_system::<Mesh>,
+                    track_live_assets_system::<StandardMaterial>,
+                    track_live_assets_system::<LineMaterial>,
+                ),
+            )
+            .add_systems(Update, asset_stats_window_system);
+    }
+}
+
+/// Assets that still have a strong handle, by type. Render-only assets are
+/// dropped from `Assets` once they're on the gpu, so `Assets::len` can't be
+/// used to spot leaks; this follows the asset events instead.
+#[derive(Resource, Default)]
+pub struct LiveAssets {
+    ids: BTreeMap<&'static str, HashSet<UntypedAssetId>>,
+}
+
+impl LiveAssets {
+    pub fn counts(&self) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+        self.ids.iter().map(|(name, ids)| (*name, ids.len()))
+    }
+}
+
+fn track_live_assets_system<A: Asset>(
+    mut events: EventReader<AssetEvent<A>>,
+    mut live: ResMut<LiveAssets>,
+) {
+    for event in events.read() {
+        let ids = live.ids.entry(A::short_type_path()).or_default();
+        match event {
+            AssetEvent::Added { id } => {
+                ids.insert(id.untyped());
+            }
+            AssetEvent::Unused { id } => {
+                ids.remove(&id.untyped());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn asset_stats_window_system(
+    mut contexts: EguiContexts,
+    state: Res<UiState>,
+    live: Res<LiveAssets>,
+) {
+    if !state.show_debug {
+        return;
+    }
+
+    egui::Window::new("Live Assets")
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("live_assets").show(ui, |ui| {
+                for (name, count) in live.counts() {
+                    ui.label(name);
+                    ui.label(count.to_string());
+                    ui.end_row();
+                }
+            });
+        });
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use bevy::{
+    ecs::component::Component,
+    math::{IRect, Vec2},
+};
+use image::{ImageBuffer, Rgba};
+
+use super::contours::distance_to_segment;
+
+/// A brush footprint in image pixels.
+pub enum PixelShape {
+    Circle { center: Vec2, radius: f32 },
+    Square { center: Vec2, half_size: f32 },
+    /// a capsule around the segment from `a` to `b`
+    Segment { a: Vec2, b: Vec2, radius: f32 },
+    Polygon { points: Vec<Vec2> },
+}
+
+impl PixelShape {
+    /// Signed distance to the edge of the shape, negative inside.
+    fn distance(&self, p: Vec2) -> f32 {
+        match self {
+            PixelShape::Circle { center, radius } => p.distance(*center) - radius,
+            PixelShape::Square { center, half_size } => {
+                let d = (p - *center).abs();
+                d.x.max(d.y) - half_size
+            }
+            PixelShape::Segment { a, b, radius } => distance_to_segment(p, *a, *b) - radius,
+            PixelShape::Polygon { points } => {
+                let edge = polygon_edges(points)
+                    .map(|(a, b)| distance_to_segment(p, a, b))
+                    .fold(f32::MAX, f32::min);
+                match point_in_polygon(p, points) {
+                    true => -edge,
+                    false => edge,
+                }
+            }
+        }
+    }
+
+    fn bounds(&self) -> (Vec2, Vec2) {
+        match self {
+            PixelShape::Circle { center, radius } => (*center - *radius, *center + *radius),
+            PixelShape::Square { center, half_size } => {
+                (*center - *half_size, *center + *half_size)
+            }
+            PixelShape::Segment { a, b, radius } => (a.min(*b) - *radius, a.max(*b) + *radius),
+            PixelShape::Polygon { points } => points.iter().fold(
+                (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+                |(min, max), p| (min.min(*p), max.max(*p)),
+            ),
+        }
+    }
+}
+
+/// The pixel rect `shape` can touch with a soft edge of `falloff`, clamped to
+/// the image.
+pub fn pixel_bounds(shape: &PixelShape, falloff: f32, width: u32, height: u32) -> Option<IRect> {
+    if matches!(shape, PixelShape::Polygon { points } if points.len() < 3) {
+        return None;
+    }
+
+    let aa_edge = falloff.max(0.5);
+    let (min, max) = shape.bounds();
+    let bounds = IRect::from_corners(
+        (min - aa_edge - 1.).floor().as_ivec2(),
+        (max + aa_edge + 2.).ceil().as_ivec2(),
+    )
+    .intersect(IRect::new(0, 0, width as i32, height as i32));
+
+    (!bounds.is_empty()).then_some(bounds)
+}
+
+/// The pixels a stroke in progress has painted, with their value before the
+/// stroke and the most they have been covered. Every stamp blends from the
+/// value before the stroke, so overlapping stamps never add up and a stroke
+/// reaches its strength whatever the cursor speed or stamp spacing.
+#[derive(Component, Default)]
+pub struct StrokeCoverage {
+    pixels: HashMap<(u32, u32), ([u8; 4], f32)>,
+}
+
+impl StrokeCoverage {
+    /// Starts a new stroke.
+    pub fn clear(&mut self) {
+        self.pixels.clear();
+    }
+}
+
+/// Paints `shape` into the image with value `v` and returns the pixel rect it
+/// touched. `falloff` is the width of the soft edge in pixels and `strength`
+/// scales how far each pixel moves towards `v`. Within a `stroke`, pixels
+/// already painted harder are left alone, without one every pixel blends from
+/// its current value.
+pub fn paint(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    shape: &PixelShape,
+    falloff: f32,
+    strength: f32,
+    v: u8,
+    mut stroke: Option<&mut StrokeCoverage>,
+) -> Option<IRect> {
+    let (width, height) = image.dimensions();
+
+    // The thickness of the antialiasing edge in pixels.
+    let aa_edge = falloff.max(0.5);
+
+    // only visit the pixels the brush can reach
+    let bounds = pixel_bounds(shape, falloff, width, height)?;
+
+    let strength = strength.clamp(0., 1.);
+    for y_coord in bounds.min.y as u32..bounds.max.y as u32 {
+        for x_coord in bounds.min.x as u32..bounds.max.x as u32 {
+            let distance = shape.distance(Vec2::new(x_coord as f32, y_coord as f32));
+            let coverage = ((aa_edge - distance) / (2.0 * aa_edge)).clamp(0.0, 1.0);
+            let alpha = coverage * strength;
+            if alpha <= 0. {
+                continue;
+            }
+
+            let before = match stroke.as_deref_mut() {
+                Some(stroke) => {
+                    let (before, painted) = stroke
+                        .pixels
+                        .entry((x_coord, y_coord))
+                        .or_insert_with(|| (image.get_pixel(x_coord, y_coord).0, 0.));
+                    if alpha <= *painted {
+                        continue;
+                    }
+                    *painted = alpha;
+                    *before
+                }
+                None => image.get_pixel(x_coord, y_coord).0,
+            };
+
+            if alpha >= 1. {
+                image.put_pixel(x_coord, y_coord, Rgba([v, v, v, 255]));
+            } else {
+                let blended_color = blend(before, [v, v, v, (255.0 * alpha) as u8]);
+                image.put_pixel(x_coord, y_coord, Rgba(blended_color));
+            }
+        }
+    }
+
+    Some(bounds)
+}
+
+// Blend two colors together based on alpha
+fn blend(color1: [u8; 4], color2: [u8; 4]) -> [u8; 4] {
+    let alpha = color2[3] as f32 / 255.0;
+    let inv_alpha = 1.0 - alpha;
+    [
+        (color1[0] as f32 * inv_alpha + color2[0] as f32 * alpha) as u8,
+        (color1[1] as f32 * inv_alpha + color2[1] as f32 * alpha) as u8,
+        (color1[2] as f32 * inv_alpha + color2[2] as f32 * alpha) as u8,
+        color1[3].saturating_add(color2[3]), // Ensure alpha doesn't exceed 255
+    ]
+}
+
+fn polygon_edges(points: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| (*a, *b))
+}
+
+fn point_in_polygon(p: Vec2, points: &[Vec2]) -> bool {
+    let mut inside = false;
+    for (a, b) in polygon_edges(points) {
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+    }
+    inside
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{
+    ecs::component::Component,
+    math::{IRect, IVec2, Rect, Vec2},
+};
+use planet::types::PolyLines;
+
+use super::contours::simplify_polyline;
+use super::planet_space::pixel_to_local;
+
+/// Side length of a collider chunk in planet local [-1, 1] space.
+pub const CHUNK_SIZE: f32 = 0.125;
+
+/// A collider holding every contour segment that falls inside one chunk.
+#[derive(Component)]
+pub struct TerrainChunk {
+    pub coord: IVec2,
+}
+
+/// Polyline vertices and segment indices for one chunk.
+#[derive(Default)]
+pub struct ChunkSegments {
+    pub vertices: Vec<Vec2>,
+    pub indices: Vec<[u32; 2]>,
+}
+
+impl ChunkSegments {
+    /// Every segment of `lines`, consecutive segments sharing their vertex.
+    pub fn from_polylines(lines: &PolyLines) -> Self {
+        let mut segments = ChunkSegments::default();
+        for line in lines.iter().filter(|line| line.len() > 1) {
+            let start = segments.vertices.len() as u32;
+            segments.vertices.extend_from_slice(line);
+            segments
+                .indices
+                .extend((start..start + line.len() as u32 - 1).map(|i| [i, i + 1]));
+        }
+        segments
+    }
+}
+
+pub fn chunk_coord(p: Vec2) -> IVec2 {
+    (p / CHUNK_SIZE).floor().as_ivec2()
+}
+
+pub fn chunk_rect(coord: IVec2) -> Rect {
+    let min = coord.as_vec2() * CHUNK_SIZE;
+    Rect::from_corners(min, min + Vec2::splat(CHUNK_SIZE))
+}
+
+/// Chunks overlapping a pixel rect of an image with the given dimension.
+pub fn chunks_in_pixel_rect(region: IRect, dimension: u32) -> HashSet<IVec2> {
+    let a = chunk_coord(pixel_to_local(region.min.as_vec2(), dimension));
+    let b = chunk_coord(pixel_to_local(region.max.as_vec2(), dimension));
+    let (min, max) = (a.min(b), a.max(b));
+
+    let mut chunks = HashSet::new();
+    for x in min.x..=max.x {
+        for y in min.y..=max.y {
+            chunks.insert(IVec2::new(x, y));
+        }
+    }
+    chunks
+}
+
+/// Cuts a contour into runs of segments by chunk, each segment going to the
+/// chunk holding its midpoint. Neighbouring runs share their end vertex.
+fn chunk_runs(line: &[Vec2]) -> Vec<(IVec2, Vec<Vec2>)> {
+    let mut runs: Vec<(IVec2, Vec<Vec2>)> = Vec::new();
+    for segment in line.windows(2) {
+        let coord = chunk_coord((segment[0] + segment[1]) / 2.);
+        match runs.last_mut() {
+            Some((run_coord, run)) if *run_coord == coord => run.push(segment[1]),
+            _ => runs.push((coord, vec![segment[0], segment[1]])),
+        }
+    }
+    runs
+}
+
+/// Splits the contours into per chunk polylines, each segment going to the
+/// chunk holding its midpoint. Pieces in neighbouring chunks share their end
+/// vertex. With `only` set, other chunks are skipped.
+pub fn chunk_polylines(
+    contours: &PolyLines,
+    only: Option<&HashSet<IVec2>>,
+) -> HashMap<IVec2, PolyLines> {
+    let mut chunks: HashMap<IVec2, PolyLines> = HashMap::new();
+    for line in contours {
+        for (coord, run) in chunk_runs(line) {
+            if only.map_or(true, |only| only.contains(&coord)) {
+                chunks.entry(coord).or_default().push(run);
+            }
+        }
+    }
+    chunks
+}
+
+/// Simplifies every contour chunk by chunk, with a tolerance in image pixels,
+/// and joins the runs back up. The part of a contour in one chunk only depends
+/// on what the contour does there, so edits elsewhere leave it alone.
+pub fn simplify_by_chunk(contours: &PolyLines, tolerance_px: f32, dimension: u32) -> PolyLines {
+    let tolerance = tolerance_px * 2. / dimension as f32;
+    contours
+        .iter()
+        .map(|line| {
+            let mut simplified: Vec<Vec2> = Vec::with_capacity(line.len());
+            for (_, run) in chunk_runs(line) {
+                let run = simplify_polyline(&run, tolerance);
+                let shared = usize::from(!simplified.is_empty());
+                simplified.extend_from_slice(&run[shared..]);
+            }
+            simplified
+        })
+        .collect()
+}
+
+pub fn contour_bounds(contours: &PolyLines) -> Vec<Rect> {
+    contours
+        .iter()
+        .map(|line| {
+            let start = line.first().copied().unwrap_or_default();
+            line.iter()
+                .fold(Rect::from_center_size(start, Vec2::ZERO), |rect, p| {
+                    rect.union_point(*p)
+                })
+        })
+        .collect()
+}
+
+/// Sutherland-Hodgman clip of a closed contour to `rect`. Where the contour
+/// leaves the rect the result runs along its edge instead.
+pub fn clip_contour(line: &[Vec2], rect: Rect) -> Vec<Vec2> {
+    let mut points = match line.first() == line.last() {
+        true => line[..line.len() - 1].to_vec(),
+        false => line.to_vec(),
+    };
+
+    // (axis, bound, keep the side above the bound)
+    let edges = [
+        (0, rect.min.x, true),
+        (0, rect.max.x, false),
+        (1, rect.min.y, true),
+        (1, rect.max.y, false),
+    ];
+    for (axis, bound, above) in edges {
+        let inside = |p: Vec2| match above {
+            true => p[axis] >= bound,
+            false => p[axis] <= bound,
+        };
+        let cross = |a: Vec2, b: Vec2| {
+            let mut p = a.lerp(b, (bound - a[axis]) / (b[axis] - a[axis]));
+            p[axis] = bound;
+            p
+        };
+
+        let mut clipped = Vec::with_capacity(points.len());
+        for (i, &current) in points.iter().enumerate() {
+            let previous = points[(i + points.len() - 1) % points.len()];
+            match (inside(previous), inside(current)) {
+                (true, true) => clipped.push(current),
+                (true, false) => clipped.push(cross(previous, current)),
+                (false, true) => {
+                    clipped.push(cross(previous, current));
+                    clipped.push(current);
+                }
+                (false, false) => {}
+            }
+        }
+        points = clipped;
+    }
+
+    if points.len() >= 3 {
+        points.push(points[0]);
+    }
+    points
+}
+
+/// The closed contours that reach into a chunk, clipped to it. Filled even-odd
+/// they cover the same part of the chunk the whole contours do, so chunks can
+/// be triangulated on their own. `bounds` comes from `contour_bounds`.
+pub fn clip_contours_to_chunk(contours: &PolyLines, bounds: &[Rect], coord: IVec2) -> PolyLines {
+    let rect = chunk_rect(coord);
+    contours
+        .iter()
+        .zip(bounds)
+        .filter(|(line, bounds)| line.len() >= 3 && !bounds.intersect(rect).is_empty())
+        .map(|(line, _)| clip_contour(line, rect))
+        .filter(|line| line.len() >= 4)
+        .collect()
+}
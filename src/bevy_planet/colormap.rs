@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum Colormap {
+    #[default]
+    Grayscale,
+    Viridis,
+    Magma,
+    /// blue below zero, red above, white at zero
+    Diverging,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum Normalization {
+    #[default]
+    MinMax,
+    /// 2nd to 98th percentile, so a few outliers don't flatten the rest
+    Percentile,
+}
+
+/// How float and byte map views are turned into colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapStyle {
+    pub colormap: Colormap,
+    pub normalization: Normalization,
+}
+
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84],
+    [71, 44, 122],
+    [59, 81, 139],
+    [44, 113, 142],
+    [33, 144, 141],
+    [39, 173, 129],
+    [92, 200, 99],
+    [170, 220, 50],
+    [253, 231, 37],
+];
+
+const MAGMA: [[u8; 3]; 9] = [
+    [0, 0, 4],
+    [28, 16, 68],
+    [79, 18, 123],
+    [129, 37, 129],
+    [181, 54, 122],
+    [229, 80, 100],
+    [251, 135, 97],
+    [254, 194, 135],
+    [252, 253, 191],
+];
+
+const DIVERGING: [[u8; 3]; 5] = [
+    [5, 48, 97],
+    [67, 147, 195],
+    [247, 247, 247],
+    [214, 96, 77],
+    [103, 0, 31],
+];
+
+impl Colormap {
+    /// Opaque color for `t` in 0..1.
+    pub fn sample(&self, t: f32) -> [u8; 4] {
+        let t = if t.is_nan() { 0. } else { t.clamp(0., 1.) };
+        let [r, g, b] = match self {
+            Colormap::Grayscale => {
+                let v = (t * 255.).round() as u8;
+                [v, v, v]
+            }
+            Colormap::Viridis => interpolate(&VIRIDIS, t),
+            Colormap::Magma => interpolate(&MAGMA, t),
+            Colormap::Diverging => interpolate(&DIVERGING, t),
+        };
+        [r, g, b, 255]
+    }
+}
+
+fn interpolate(stops: &[[u8; 3]], t: f32) -> [u8; 3] {
+    let position = t * (stops.len() - 1) as f32;
+    let i = (position.floor() as usize).min(stops.len() - 2);
+    let f = position - i as f32;
+    let (a, b) = (stops[i], stops[i + 1]);
+    [0, 1, 2].map(|c| (a[c] as f32 + (b[c] as f32 - a[c] as f32) * f).round() as u8)
+}
+
+impl MapStyle {
+    /// The value range mapped onto the colormap. Diverging maps are centered on zero.
+    pub fn range(&self, values: impl Iterator<Item = f32>) -> (f32, f32) {
+        let mut values: Vec<f32> = values.filter(|v| v.is_finite()).collect();
+        if values.is_empty() {
+            return (0., 1.);
+        }
+
+        let (min, max) = match self.normalization {
+            Normalization::MinMax => values
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(*v), max.max(*v))),
+            Normalization::Percentile => {
+                let last = values.len() - 1;
+                let low = last * 2 / 100;
+                let high = last * 98 / 100;
+                let min = *values.select_nth_unstable_by(low, f32::total_cmp).1;
+                let max = *values.select_nth_unstable_by(high, f32::total_cmp).1;
+                (min, max)
+            }
+        };
+
+        match self.colormap {
+            Colormap::Diverging => {
+                let extent = min.abs().max(max.abs());
+                (-extent, extent)
+            }
+            _ => (min, max),
+        }
+    }
+
+    pub fn color(&self, v: f32, (min, max): (f32, f32)) -> [u8; 4] {
+        let span = max - min;
+        let t = if span > f32::EPSILON { (v - min) / span } else { 0.5 };
+        self.colormap.sample(t)
+    }
+}
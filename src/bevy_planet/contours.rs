@@ -0,0 +1,410 @@
+use bevy::math::{IRect, Vec2};
+use image::{imageops, ImageBuffer, Rgba};
+use planet::{planet_data::march_squares_rgba, types::PolyLines};
+
+use super::planet_space::{local_to_pixel, pixel_to_local};
+
+// extra pixels around an edit that marching squares can see change
+const MARCH_MARGIN: i32 = 2;
+
+// how far apart two marches may put the same vertex, in pixels
+const STITCH_TOLERANCE: f32 = 0.01;
+
+/// A run of one contour on one side of the region border. A cut end carries
+/// the vertex just across the border, so the edge it ends on crosses it.
+struct ContourPiece {
+    points: Vec<Vec2>,
+    head_cut: bool,
+    tail_cut: bool,
+}
+
+enum ContourSplit {
+    /// every vertex is on the kept side
+    Whole,
+    /// no vertex is on the kept side
+    Dropped,
+    Pieces(Vec<ContourPiece>),
+}
+
+/// Cuts a contour into the runs of vertices for which `keep` holds.
+fn split_contour(line: &[Vec2], keep: impl Fn(Vec2) -> bool) -> ContourSplit {
+    let closed = line.len() > 2 && line.first() == line.last();
+    let points = match closed {
+        true => &line[..line.len() - 1],
+        false => line,
+    };
+    let kept: Vec<bool> = points.iter().map(|p| keep(*p)).collect();
+    let n = points.len();
+    if kept.iter().all(|k| *k) {
+        return ContourSplit::Whole;
+    }
+    if !kept.iter().any(|k| *k) {
+        return ContourSplit::Dropped;
+    }
+
+    let mut pieces = Vec::new();
+    let mut current: Option<ContourPiece> = None;
+    // a closed contour is walked round from a dropped vertex back to it, so
+    // every run gets cut on both ends
+    let (first, last) = match closed {
+        true => {
+            let start = kept.iter().position(|k| !k).unwrap_or(0);
+            (start, start + n)
+        }
+        false => (0, n - 1),
+    };
+    for i in first..=last {
+        let (index, previous) = (i % n, (i + n - 1) % n);
+        if !kept[index] {
+            if let Some(mut piece) = current.take() {
+                piece.points.push(points[index]);
+                piece.tail_cut = true;
+                pieces.push(piece);
+            }
+            continue;
+        }
+        match &mut current {
+            Some(piece) => piece.points.push(points[index]),
+            None => {
+                let head_cut = closed || i > 0;
+                let mut piece = ContourPiece {
+                    points: Vec::new(),
+                    head_cut,
+                    tail_cut: false,
+                };
+                if head_cut {
+                    piece.points.push(points[previous]);
+                }
+                piece.points.push(points[index]);
+                current = Some(piece);
+            }
+        }
+    }
+    // an open contour ending on the kept side
+    pieces.extend(current);
+
+    ContourSplit::Pieces(pieces)
+}
+
+/// Joins pieces cut from the old and new contours on the border edges they
+/// share. `None` if some cut end has no partner, the marches disagree there.
+fn stitch_pieces(pieces: Vec<ContourPiece>, tolerance: f32) -> Option<PolyLines> {
+    let same = |a: Vec2, b: Vec2| a.distance_squared(b) <= tolerance * tolerance;
+    let mut used = vec![false; pieces.len()];
+    let mut stitched = Vec::new();
+
+    // open contours are walked from an open end, so they come out in one go
+    let mut order: Vec<usize> = (0..pieces.len()).collect();
+    order.sort_by_key(|i| pieces[*i].head_cut);
+
+    for start in order {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut chain = pieces[start].points.clone();
+        let mut head_cut = pieces[start].head_cut;
+        let mut tail_cut = pieces[start].tail_cut;
+
+        loop {
+            if !tail_cut {
+                if !head_cut {
+                    break;
+                }
+                // ran into the open end of a contour, carry on from the other end
+                chain.reverse();
+                (head_cut, tail_cut) = (false, true);
+                continue;
+            }
+
+            let n = chain.len();
+            let (a, b) = (chain[n - 2], chain[n - 1]);
+            if head_cut && n > 3 && same(chain[0], a) && same(chain[1], b) {
+                // back round to the start, close the loop
+                chain.pop();
+                chain[n - 2] = chain[0];
+                break;
+            }
+
+            let next = (0..pieces.len()).find_map(|i| {
+                let piece = &pieces[i];
+                let m = piece.points.len();
+                if used[i] {
+                    None
+                } else if piece.head_cut && same(piece.points[0], a) && same(piece.points[1], b)
+                {
+                    Some((i, false))
+                } else if piece.tail_cut
+                    && same(piece.points[m - 1], a)
+                    && same(piece.points[m - 2], b)
+                {
+                    Some((i, true))
+                } else {
+                    None
+                }
+            });
+            let (i, reversed) = next?;
+            used[i] = true;
+            let piece = &pieces[i];
+            match reversed {
+                false => {
+                    chain.extend_from_slice(&piece.points[2..]);
+                    tail_cut = piece.tail_cut;
+                }
+                true => {
+                    chain.extend(piece.points.iter().rev().skip(2));
+                    tail_cut = piece.head_cut;
+                }
+            }
+        }
+
+        stitched.push(chain);
+    }
+
+    Some(stitched)
+}
+
+/// Re-marches only the part of `image` around `dirty` and splices the result
+/// into `contours`. Contours crossing the border of the returned region are
+/// cut there and the parts inside it replaced by the new march, joined back on
+/// the border edges both marches share. If they don't line up the whole image
+/// is marched instead and the image rect returned.
+pub fn remarch_region(
+    image: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    contours: &mut PolyLines,
+    dirty: IRect,
+) -> anyhow::Result<IRect> {
+    let dimension = image.width();
+    let image_rect = IRect::new(0, 0, image.width() as i32, image.height() as i32);
+
+    let region = dirty.inset(MARCH_MARGIN).intersect(image_rect);
+    // the crop reaches well past the region, so the edges crossing its border
+    // come out the same as in a full march
+    let crop_rect = region.inset(2 * MARCH_MARGIN).intersect(image_rect);
+
+    // march a square crop, so its normalisation matches the full image
+    let side = crop_rect.width().max(crop_rect.height()).max(1) as u32;
+    let mut crop: ImageBuffer<Rgba<u8>, Vec<u8>> = ImageBuffer::new(side, side);
+    let source = imageops::crop_imm(
+        image,
+        crop_rect.min.x as u32,
+        crop_rect.min.y as u32,
+        crop_rect.width() as u32,
+        crop_rect.height() as u32,
+    )
+    .to_image();
+    imageops::replace(&mut crop, &source, 0, 0);
+
+    let offset = crop_rect.min.as_vec2();
+    let new_contours: PolyLines = march_squares_rgba(&crop)
+        .map_err(|err| anyhow::anyhow!("{}", err))?
+        .into_iter()
+        .map(|line| {
+            line.into_iter()
+                .map(|p| {
+                    let pixel = local_to_pixel(p, side) + offset;
+                    pixel_to_local(pixel, dimension)
+                })
+                .collect::<Vec<Vec2>>()
+        })
+        .collect();
+
+    // sides of the region on the image border don't count, nothing crosses them
+    let d = dimension as f32;
+    let inside = |p: Vec2| {
+        let p = local_to_pixel(p, dimension);
+        (region.min.x <= 0 || p.x > region.min.x as f32)
+            && (region.min.y <= 0 || p.y > region.min.y as f32)
+            && (region.max.x as f32 >= d || p.x < region.max.x as f32)
+            && (region.max.y as f32 >= d || p.y < region.max.y as f32)
+    };
+
+    let mut kept = Vec::new();
+    let mut pieces = Vec::new();
+    for line in contours.iter() {
+        match split_contour(line, |p| !inside(p)) {
+            ContourSplit::Whole => kept.push(line.clone()),
+            ContourSplit::Dropped => {}
+            ContourSplit::Pieces(cut) => pieces.extend(cut),
+        }
+    }
+    for line in new_contours {
+        match split_contour(&line, inside) {
+            ContourSplit::Whole => kept.push(line),
+            ContourSplit::Dropped => {}
+            ContourSplit::Pieces(cut) => pieces.extend(cut),
+        }
+    }
+
+    match stitch_pieces(pieces, STITCH_TOLERANCE * 2. / dimension as f32) {
+        Some(stitched) => {
+            kept.extend(stitched);
+            *contours = kept;
+            Ok(region)
+        }
+        None => {
+            *contours = march_squares_rgba(image).map_err(|err| anyhow::anyhow!("{}", err))?;
+            Ok(image_rect)
+        }
+    }
+}
+
+pub fn union_region(a: Option<IRect>, b: IRect) -> IRect {
+    match a {
+        Some(a) => a.union(b),
+        None => b,
+    }
+}
+
+/// Douglas-Peucker simplification. `tolerance` is in the same units as the points,
+/// the end points are always kept so closed contours stay closed.
+pub fn simplify_polyline(line: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if line.len() < 3 || tolerance <= 0. {
+        return line.to_vec();
+    }
+
+    let mut keep = vec![false; line.len()];
+    keep[0] = true;
+    keep[line.len() - 1] = true;
+
+    let mut stack = vec![(0, line.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let mut max_distance = 0.;
+        let mut max_index = start;
+        for i in start + 1..end {
+            let distance = distance_to_segment(line[i], line[start], line[end]);
+            if distance > max_distance {
+                max_distance = distance;
+                max_index = i;
+            }
+        }
+
+        if max_distance > tolerance {
+            keep[max_index] = true;
+            stack.push((start, max_index));
+            stack.push((max_index, end));
+        }
+    }
+
+    line.iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(p, _)| *p)
+        .collect()
+}
+
+/// Simplifies every contour with a tolerance given in image pixels.
+pub fn simplify_polylines(lines: &PolyLines, tolerance_px: f32, dimension: u32) -> PolyLines {
+    let tolerance = tolerance_px * 2. / dimension as f32;
+    lines
+        .iter()
+        .map(|line| simplify_polyline(line, tolerance))
+        .collect()
+}
+
+pub fn vertex_count(lines: &PolyLines) -> usize {
+    lines.iter().map(|line| line.len()).sum()
+}
+
+pub fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let length_squared = ab.length_squared();
+    if length_squared == 0. {
+        // closed contours start and end on the same point
+        return p.distance(a);
+    }
+    let t = ((p - a).dot(ab) / length_squared).clamp(0., 1.);
+    p.distance(a + ab * t)
+}
+
+/// Chaikin corner cutting. Closed contours (first point == last point) are
+/// cut all the way round, open ones keep their end points.
+pub fn smooth_polyline(line: &[Vec2], iterations: u32) -> Vec<Vec2> {
+    let mut line = line.to_vec();
+    for _ in 0..iterations {
+        if line.len() < 3 {
+            break;
+        }
+        let closed = line.first() == line.last();
+
+        let mut smoothed = Vec::with_capacity(line.len() * 2);
+        if !closed {
+            smoothed.push(line[0]);
+        }
+        for segment in line.windows(2) {
+            smoothed.push(segment[0].lerp(segment[1], 0.25));
+            smoothed.push(segment[0].lerp(segment[1], 0.75));
+        }
+        if closed {
+            smoothed.push(smoothed[0]);
+        } else {
+            smoothed.push(line[line.len() - 1]);
+        }
+        line = smoothed;
+    }
+    line
+}
+
+pub fn smooth_polylines(lines: &PolyLines, iterations: u32) -> PolyLines {
+    lines
+        .iter()
+        .map(|line| smooth_polyline(line, iterations))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(points: &[(f32, f32)]) -> Vec<Vec2> {
+        points.iter().map(|(x, y)| Vec2::new(*x, *y)).collect()
+    }
+
+    /// Cuts `old` and `new` at x = 1 like `remarch_region` does, keeping the
+    /// old contour left of it and the new one right of it.
+    fn splice(old: &[Vec2], new: &[Vec2]) -> Option<PolyLines> {
+        let inside = |p: Vec2| p.x > 1.;
+        let mut pieces = Vec::new();
+        for split in [split_contour(old, |p| !inside(p)), split_contour(new, inside)] {
+            match split {
+                ContourSplit::Pieces(cut) => pieces.extend(cut),
+                _ => panic!("both contours cross the border"),
+            }
+        }
+        stitch_pieces(pieces, 1e-4)
+    }
+
+    fn old_loop() -> Vec<Vec2> {
+        v(&[(-2., -1.), (0., -1.), (2., -1.), (2., 1.), (0., 1.), (-2., 1.), (-2., -1.)])
+    }
+
+    #[test]
+    fn stitches_a_cut_loop_back_together() {
+        // the re-marched part bulges out to x = 3
+        let new = v(&[(0., -1.), (2., -1.), (3., 0.), (2., 1.), (0., 1.)]);
+        let stitched = splice(&old_loop(), &new).unwrap();
+
+        assert_eq!(stitched.len(), 1);
+        let line = &stitched[0];
+        assert_eq!(line.first(), line.last());
+        assert_eq!(line.len(), 8);
+        assert!(line.contains(&Vec2::new(3., 0.)));
+        assert!(line.contains(&Vec2::new(-2., 1.)));
+    }
+
+    #[test]
+    fn stitches_a_new_piece_running_the_other_way() {
+        let new = v(&[(0., 1.), (2., 1.), (3., 0.), (2., -1.), (0., -1.)]);
+        let stitched = splice(&old_loop(), &new).unwrap();
+
+        assert_eq!(stitched.len(), 1);
+        assert_eq!(stitched[0].first(), stitched[0].last());
+        assert_eq!(stitched[0].len(), 8);
+    }
+
+    #[test]
+    fn mismatched_cut_is_rejected() {
+        let new = v(&[(0., -1.5), (2., -1.), (3., 0.), (2., 1.), (0., 1.)]);
+        assert!(splice(&old_loop(), &new).is_none());
+    }
+}
@@ -8,11 +8,16 @@ use bevy::render::{
 };
 use image::{ImageBuffer, Rgba};
 use planet::{
+    planet_data::PlanetData,
     room::Room,
     tile_map::{Status, Tile, TileMap},
 };
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
+use crate::ui_state::BitmapDisplay;
+
+use super::colormap::MapStyle;
+
 pub const GREY: [u8; 4] = [128, 128, 128, 255];
 pub const BLACK: [u8; 4] = [0, 0, 0, 255];
 pub const WHITE: [u8; 4] = [255, 255, 255, 255];
@@ -21,8 +26,41 @@ pub const GREEN: [u8; 4] = [0, 255, 0, 255];
 pub const BLUE: [u8; 4] = [0, 0, 255, 255];
 pub const ORANGE: [u8; 4] = [255, 165, 0, 255];
 
+/// The bitmap layer a `BitmapDisplay` shows, if the planet has it.
+pub fn bitmap_display_to_bevy_image(
+    planet: &PlanetData,
+    display: &BitmapDisplay,
+    style: &MapStyle,
+) -> Option<Image> {
+    match display {
+        BitmapDisplay::PlanetRaw => Some(umap_to_bevy_image(&planet.planet_map.main, style)),
+        BitmapDisplay::PlanetProcessed => Some(imagebuffer_to_bevy_image(&planet.image)),
+        BitmapDisplay::Altitude => Some(fmap_to_bevy_image(&planet.planet_map.altitude, style)),
+        BitmapDisplay::Depth => Some(fmap_to_bevy_image(&planet.planet_map.depth, style)),
+        BitmapDisplay::Mask => planet
+            .planet_map
+            .mask
+            .as_ref()
+            .map(|map| fmap_to_bevy_image(map, style)),
+        BitmapDisplay::RoomsRaw => planet
+            .planet_map
+            .rooms_raw
+            .as_ref()
+            .map(|map| umap_to_bevy_image(map, style)),
+        BitmapDisplay::RoomsDebug => planet
+            .roooms
+            .as_ref()
+            .map(|roooms| room_vec_to_bevy_image(&roooms.rooms, planet.tile_map.len())),
+        BitmapDisplay::TileMapDebug => Some(tile_map_to_bevy_image(&planet.tile_map)),
+    }
+}
+
+/// Back to an image buffer, for writing a converted layer to disk.
+pub fn bevy_image_to_imagebuffer(image: &Image) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    ImageBuffer::from_raw(image.width(), image.height(), image.data.clone())
+}
+
 pub fn imagebuffer_to_bevy_image(buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Image {
-    println!("imagebuffer_to_bevy_image");
     let width = buffer.width();
     let height = buffer.height();
 
@@ -40,37 +78,16 @@ pub fn imagebuffer_to_bevy_image(buffer: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Ima
     Image::new(size, dimension, data.clone(), format, asset_usage)
 }
 
-pub fn umap_to_bevy_image(map: &Vec<Vec<u8>>) -> Image {
-    // println!("umap_to_bevy_image");
-    let width = map.len() as u32;
-    let height = map[0].len() as u32;
-
-    let size = Extent3d {
-        width,
-        height,
-        depth_or_array_layers: 1,
-    };
-
-    let dimension = TextureDimension::D2;
-
-    let data: Vec<u8> = (0..map[0].len()) // Iterate over columns first.
-    .flat_map(|col| {
-        map.iter() // Then iterate over rows.
-            .flat_map(move |row| {
-                let v = (row[col] * 100) as u8; // Access the element at the current column in the current row and convert.
-                vec![v, v, v, 10u8] // R, G, B, A
-            })
-    })
-    .collect();
-
-    let format = TextureFormat::Rgba8UnormSrgb;
-    let asset_usage = RenderAssetUsages::RENDER_WORLD;
+pub fn umap_to_bevy_image(map: &Vec<Vec<u8>>, style: &MapStyle) -> Image {
+    scalar_map_to_bevy_image(map, style, |v| *v as f32)
+}
 
-    Image::new(size, dimension, data, format, asset_usage)
+pub fn fmap_to_bevy_image(map: &Vec<Vec<f32>>, style: &MapStyle) -> Image {
+    scalar_map_to_bevy_image(map, style, |v| *v)
 }
 
-pub fn fmap_to_bevy_image(map: &Vec<Vec<f32>>) -> Image {
-    println!("fmap_to_bevy_image");
+/// Maps indexed [x][y] are normalized with `style` and run through its colormap.
+fn scalar_map_to_bevy_image<T>(map: &[Vec<T>], style: &MapStyle, value: impl Fn(&T) -> f32) -> Image {
     let width = map.len() as u32;
     let height = map[0].len() as u32;
 
@@ -82,11 +99,12 @@ pub fn fmap_to_bevy_image(map: &Vec<Vec<f32>>) -> Image {
 
     let dimension = TextureDimension::D2;
 
-    let mut data: Vec<u8> = vec![];
-    for y in 0..height {
-        for x in 0..width {
-            let v = (map[x as usize][y as usize] * 100.0) as u8;
-            data.append(vec![v, v, v, 10u8].as_mut());
+    let range = style.range(map.iter().flatten().map(&value));
+
+    let mut data: Vec<u8> = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            data.extend_from_slice(&style.color(value(&map[x][y]), range));
         }
     }
 
@@ -96,8 +114,32 @@ pub fn fmap_to_bevy_image(map: &Vec<Vec<f32>>) -> Image {
     Image::new(size, dimension, data, format, asset_usage)
 }
 
+/// The value range a scalar map view is normalized to, `None` for views that
+/// aren't scalar maps.
+pub fn bitmap_display_value_range(
+    planet: &PlanetData,
+    display: &BitmapDisplay,
+    style: &MapStyle,
+) -> Option<(f32, f32)> {
+    let fmap = |map: &Vec<Vec<f32>>| style.range(map.iter().flatten().copied());
+    let umap = |map: &Vec<Vec<u8>>| style.range(map.iter().flatten().map(|v| *v as f32));
+
+    match display {
+        BitmapDisplay::PlanetRaw => Some(umap(&planet.planet_map.main)),
+        BitmapDisplay::Altitude => Some(fmap(&planet.planet_map.altitude)),
+        BitmapDisplay::Depth => Some(fmap(&planet.planet_map.depth)),
+        BitmapDisplay::Mask => planet.planet_map.mask.as_ref().map(fmap),
+        BitmapDisplay::RoomsRaw => planet.planet_map.rooms_raw.as_ref().map(umap),
+        BitmapDisplay::PlanetProcessed
+        | BitmapDisplay::RoomsDebug
+        | BitmapDisplay::TileMapDebug => None,
+    }
+}
+
+/// Rooms in their per-id colors with accented edge tiles and the center in
+/// green. Room tiles are (x, y) like the tile map, so this lines up with
+/// `tile_map_to_bevy_image`.
 pub fn room_vec_to_bevy_image(room_vec: &Vec<Room>, res: usize) -> Image {
-    println!("room_vec_to_bevy_image");
     let size = Extent3d {
         width: res as u32,
         height: res as u32,
@@ -106,45 +148,29 @@ pub fn room_vec_to_bevy_image(room_vec: &Vec<Room>, res: usize) -> Image {
 
     let dimension = TextureDimension::D2;
 
-    let mut data: Vec<u8> = vec![0; res * res * 4];
+    let mut data: Vec<u8> = BLACK.repeat(res * res);
+
+    // tiles outside the image are skipped rather than wrapping onto another row
+    let mut put = |x: usize, y: usize, c: [u8; 4]| {
+        if x < res && y < res {
+            let index = (y * res + x) * 4;
+            data[index..index + 4].copy_from_slice(&c);
+        }
+    };
 
     for room in room_vec {
+        let c = random_room_color(room.id as u64);
         for tile in &room.tiles {
-            // let x = tile.y as usize;
-            // let y = res - tile.x as usize - 1;
-            let y = tile.x as usize;
-            let x = tile.y as usize;
-            let index = (x * res + y) * 4;
-            let c = random_room_color(room.id as u64);
-            data[index] = c[0]; // R
-            data[index + 1] = c[1]; // G
-            data[index + 2] = c[2]; // B
-            data[index + 3] = 255; // A (opacity)
+            put(tile.x as usize, tile.y as usize, c);
         }
 
+        let c = random_room_color_accent(room.id as u64);
         for tile in &room.edge_tile_indexes {
-            // let x = room.tiles[*tile].y as usize;
-            // let y = res - room.tiles[*tile].x as usize - 1;
-            
-            let y = room.tiles[*tile].x as usize;
-            let x = room.tiles[*tile].y as usize;
-
-            let index = (x * res + y) * 4;
-            let c = random_room_color_accent(room.id as u64);
-            data[index] = c[0]; // R
-            data[index + 1] = c[1]; // G
-            data[index + 2] = c[2]; // B
-            data[index + 3] = 255; // A (opacity)
+            let tile = &room.tiles[*tile];
+            put(tile.x as usize, tile.y as usize, c);
         }
 
-        let x = room.center.y;
-        let y = room.center.x;
-        let index = (x * res + y) * 4;
-        let c = GREEN;
-        data[index] = c[0]; // R
-        data[index + 1] = c[1]; // G
-        data[index + 2] = c[2]; // B
-        data[index + 3] = 255; // A (opacity)
+        put(room.center.x as usize, room.center.y as usize, GREEN);
     }
 
     let format = TextureFormat::Rgba8UnormSrgb;
@@ -153,9 +179,11 @@ pub fn room_vec_to_bevy_image(room_vec: &Vec<Room>, res: usize) -> Image {
     Image::new(size, dimension, data, format, asset_usage)
 }
 
+/// The tile map is indexed [x][y] like every other map, so it lines up with
+/// the processed image and `room_vec_to_bevy_image`.
 pub fn tile_map_to_bevy_image(map: &TileMap) -> Image {
-    let width = map[0].len() as u32; // Assuming all rows have the same length
-    let height = map.len() as u32;
+    let width = map.len() as u32;
+    let height = map[0].len() as u32;
 
     let size = Extent3d {
         width,
@@ -165,44 +193,22 @@ pub fn tile_map_to_bevy_image(map: &TileMap) -> Image {
 
     let dimension = TextureDimension::D2;
 
-    // let data: Vec<u8> = map
-    //     .iter()
-    //     .flat_map(|row| {
-    //         row.iter().flat_map(|&tile| {
-    //             match tile {
-    //                 Tile::Space => BLACK,
-    //                 Tile::Wall => GREY, // White for wall
-    //                 Tile::Room(id) => random_room_color((id).unwrap() as u64),
-    //                 // Tile::RoomCenter(id) => random_room_center_color((id) as u64),
-    //                 Tile::RoomCenter(id) => random_room_color_accent((id) as u64),
-    //                 _ => GREEN, // Add more cases as needed
-    //             }
-    //         })
-    //     })
-    //     .collect();
-
-    let data: Vec<u8> = (0..map[0].len()) // Iterate over columns first
-    .flat_map(|col| {
-        map.iter() // Then iterate over rows
-            .flat_map(move |row| {
-                let &tile = &row[col]; // Access the tile at the current column in the current row
-                match tile {
-                    Tile::Space => BLACK,
-                    Tile::Wall => GREY,
-                    Tile::Room(status) => {
-
-                        match status {
-                            Status::Designated(id) => random_room_color(id as u64),
-                            Status::Undesignated => WHITE,
-                        }
-                    },
-                    Tile::RoomCenter(id) => random_room_color_accent(id as u64),
-                    Tile::Tunnel(_) => ORANGE,
-                    _ => GREEN,
-                }
-            })
-    })
-    .collect();
+    let mut data: Vec<u8> = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            data.extend_from_slice(&match map[x][y] {
+                Tile::Space => BLACK,
+                Tile::Wall => GREY,
+                Tile::Room(status) => match status {
+                    Status::Designated(id) => random_room_color(id as u64),
+                    Status::Undesignated => WHITE,
+                },
+                Tile::RoomCenter(id) => random_room_color_accent(id as u64),
+                Tile::Tunnel(_) => ORANGE,
+                _ => GREEN,
+            });
+        }
+    }
 
     let format = TextureFormat::Rgba8UnormSrgb;
     let asset_usage = RenderAssetUsages::RENDER_WORLD;
@@ -0,0 +1,58 @@
+use bevy::{
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+    },
+};
+use lyon_tessellation::{
+    math::point, path::Path, BuffersBuilder, FillOptions, FillRule, FillTessellator,
+    FillVertex, VertexBuffers,
+};
+use planet::types::PolyLines;
+
+/// Triangulates the closed contours in planet local space.
+/// Uses the even-odd rule, so caves nested in the crust (and islands nested in
+/// caves) come out right whatever way marching squares wound them.
+pub fn tessellate_contours(lines: &PolyLines) -> anyhow::Result<(Vec<Vec2>, Vec<[u32; 3]>)> {
+    let mut builder = Path::builder();
+    for line in lines.iter().filter(|line| line.len() >= 3) {
+        builder.begin(point(line[0].x, line[0].y));
+        for p in &line[1..] {
+            builder.line_to(point(p.x, p.y));
+        }
+        builder.end(true);
+    }
+    let path = builder.build();
+
+    let mut buffers: VertexBuffers<Vec2, u32> = VertexBuffers::new();
+    FillTessellator::new().tessellate_path(
+        &path,
+        &FillOptions::default().with_fill_rule(FillRule::EvenOdd),
+        &mut BuffersBuilder::new(&mut buffers, |vertex: FillVertex| {
+            let p = vertex.position();
+            Vec2::new(p.x, p.y)
+        }),
+    )?;
+
+    let triangles = buffers
+        .indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect();
+    Ok((buffers.vertices, triangles))
+}
+
+pub fn contour_fill_mesh(lines: &PolyLines) -> anyhow::Result<Mesh> {
+    let (vertices, triangles) = tessellate_contours(lines)?;
+
+    let positions: Vec<[f32; 3]> = vertices.iter().map(|p| [p.x, p.y, 0.]).collect();
+    let normals = vec![[0., 0., 1.]; positions.len()];
+    Ok(Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_indices(Indices::U32(triangles.into_iter().flatten().collect())))
+}
@@ -0,0 +1,125 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::{
+    ecs::component::Component,
+    math::{IRect, IVec2},
+};
+use image::{ImageBuffer, Rgba};
+
+/// Strokes kept per planet before the oldest is dropped.
+const HISTORY_LENGTH: usize = 32;
+
+struct PixelDelta {
+    x: u32,
+    y: u32,
+    before: [u8; 4],
+    after: [u8; 4],
+}
+
+/// Bounded undo/redo of the terrain edits made to a planet's image, stored as
+/// the pixels each stroke changed.
+#[derive(Component, Default)]
+pub struct EditHistory {
+    undo: VecDeque<Vec<PixelDelta>>,
+    redo: Vec<Vec<PixelDelta>>,
+    // original values of the pixels touched by the stroke in progress
+    pending: HashMap<(u32, u32), [u8; 4]>,
+}
+
+impl EditHistory {
+    /// Remembers the current value of every pixel in `rect`, call before painting it.
+    pub fn record(&mut self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>, rect: IRect) {
+        for y in rect.min.y as u32..rect.max.y as u32 {
+            for x in rect.min.x as u32..rect.max.x as u32 {
+                self.pending
+                    .entry((x, y))
+                    .or_insert_with(|| image.get_pixel(x, y).0);
+            }
+        }
+    }
+
+    /// Makes an edit from outside the stroke in progress a step of its own:
+    /// remembers `rect`, lets `edit` change the image and closes the step,
+    /// leaving the stroke open.
+    pub fn record_step<R>(
+        &mut self,
+        image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+        rect: IRect,
+        edit: impl FnOnce(&mut ImageBuffer<Rgba<u8>, Vec<u8>>) -> R,
+    ) -> R {
+        let stroke = std::mem::take(&mut self.pending);
+        self.record(image, rect);
+        let result = edit(image);
+        self.commit(image);
+        self.pending = stroke;
+        result
+    }
+
+    /// Closes the stroke in progress, if any.
+    pub fn commit(&mut self, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let stroke: Vec<PixelDelta> = self
+            .pending
+            .drain()
+            .map(|((x, y), before)| PixelDelta {
+                x,
+                y,
+                before,
+                after: image.get_pixel(x, y).0,
+            })
+            .filter(|delta| delta.before != delta.after)
+            .collect();
+        if stroke.is_empty() {
+            return;
+        }
+
+        self.undo.push_back(stroke);
+        if self.undo.len() > HISTORY_LENGTH {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    /// Restores the image to before the last stroke and returns the pixel rect it changed.
+    pub fn undo(&mut self, image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<IRect> {
+        self.commit(image);
+        let stroke = self.undo.pop_back()?;
+        let rect = apply(image, &stroke, |delta| delta.before);
+        self.redo.push(stroke);
+        rect
+    }
+
+    pub fn redo(&mut self, image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>) -> Option<IRect> {
+        let stroke = self.redo.pop()?;
+        let rect = apply(image, &stroke, |delta| delta.after);
+        self.undo.push_back(stroke);
+        rect
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || !self.pending.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+fn apply(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    stroke: &[PixelDelta],
+    value: impl Fn(&PixelDelta) -> [u8; 4],
+) -> Option<IRect> {
+    let mut rect: Option<IRect> = None;
+    for delta in stroke {
+        image.put_pixel(delta.x, delta.y, Rgba(value(delta)));
+
+        let pixel = IVec2::new(delta.x as i32, delta.y as i32);
+        let pixel_rect = IRect::from_corners(pixel, pixel + 1);
+        rect = Some(rect.map_or(pixel_rect, |rect| rect.union(pixel_rect)));
+    }
+    rect
+}
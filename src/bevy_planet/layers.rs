@@ -0,0 +1,96 @@
+use bevy::render::texture::Image;
+use planet::planet_data::PlanetData;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::ui_state::BitmapDisplay;
+
+use super::colormap::MapStyle;
+use super::conversions::bitmap_display_to_bevy_image;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Add,
+}
+
+impl BlendMode {
+    /// Blends channel `s` over `d`, both in [0, 1].
+    fn blend(&self, s: f32, d: f32) -> f32 {
+        match self {
+            BlendMode::Normal => s,
+            BlendMode::Multiply => s * d,
+            BlendMode::Screen => 1. - (1. - s) * (1. - d),
+            BlendMode::Add => (s + d).min(1.),
+        }
+    }
+}
+
+/// A view drawn over the base `BitmapDisplay` of the texture plane.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextureLayer {
+    pub display: BitmapDisplay,
+    pub enabled: bool,
+    pub opacity: f32,
+    pub blend: BlendMode,
+}
+
+/// One disabled layer per view, in `BitmapDisplay` order.
+pub fn default_texture_layers() -> Vec<TextureLayer> {
+    BitmapDisplay::iter()
+        .map(|display| TextureLayer {
+            display,
+            enabled: false,
+            opacity: 0.5,
+            blend: BlendMode::Normal,
+        })
+        .collect()
+}
+
+/// The base view with every enabled layer blended over it in order. Layers
+/// the planet doesn't have are skipped, ones of another size are sampled
+/// nearest neighbour.
+pub fn composite_texture_layers(
+    planet: &PlanetData,
+    base: &BitmapDisplay,
+    layers: &[TextureLayer],
+    style: &MapStyle,
+) -> Option<Image> {
+    let mut image = bitmap_display_to_bevy_image(planet, base, style)?;
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+
+    for layer in layers {
+        if !layer.enabled || layer.opacity <= 0. || layer.display == *base {
+            continue;
+        }
+        let Some(overlay) = bitmap_display_to_bevy_image(planet, &layer.display, style) else {
+            continue;
+        };
+        let overlay_width = overlay.width() as usize;
+        let overlay_height = overlay.height() as usize;
+
+        for y in 0..height {
+            let oy = y * overlay_height / height;
+            for x in 0..width {
+                let ox = x * overlay_width / width;
+                let d = (y * width + x) * 4;
+                let s = (oy * overlay_width + ox) * 4;
+
+                let alpha = layer.opacity.clamp(0., 1.) * overlay.data[s + 3] as f32 / 255.;
+                for c in 0..3 {
+                    let dst = image.data[d + c] as f32 / 255.;
+                    let src = overlay.data[s + c] as f32 / 255.;
+                    let out = dst + (layer.blend.blend(src, dst) - dst) * alpha;
+                    image.data[d + c] = (out * 255.).round() as u8;
+                }
+            }
+        }
+    }
+
+    Some(image)
+}
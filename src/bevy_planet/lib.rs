@@ -1,4 +1,11 @@
-use bevy::{ecs::query, prelude::*, utils::tracing};
+use bevy::{
+    ecs::query,
+    math::IRect,
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+    utils::tracing,
+};
+use bevy_egui::EguiContexts;
 use bevy_rapier2d::prelude::*;
 use planet::{
     planet_data::{
@@ -6,258 +13,951 @@ use planet::{
         PlanetData,
     }, types::PolyLines, PlanetBuilder, PlanetOptions
 };
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     line::{LineList, LineMaterial},
-    ui::{GeneralUpdateEvent, ModifyMeshEvent, MouseClickWorldEvent, RegeneratePlanetEvent},
-    ui_state::{BitmapDisplay, UiState},
+    ui::{
+        ContoursChangedEvent, GeneralUpdateEvent, ModifyMeshEvent, MouseClickWorldEvent,
+        RegeneratePlanetEvent,
+    },
+    ui_state::{BitmapDisplay, BrushShape, ColliderContours, CollisionMode, UiState},
 };
 
 pub struct PlanetPlugin;
 
+use super::chunks::{
+    chunk_polylines, chunks_in_pixel_rect, clip_contours_to_chunk, contour_bounds,
+    simplify_by_chunk, ChunkSegments, TerrainChunk,
+};
+use super::contours::{
+    remarch_region, simplify_polylines, smooth_polylines, union_region, vertex_count,
+};
+use super::colormap::Colormap;
+use super::planet_space::PlanetSpace;
 use super::conversions::*;
+use super::layers::composite_texture_layers;
+use super::brush::{paint, pixel_bounds, PixelShape, StrokeCoverage};
+use super::history::EditHistory;
+use super::planet_file::PlanetFile;
+use super::fill::{contour_fill_mesh, tessellate_contours};
 
 impl Plugin for PlanetPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(
             MaterialPlugin::<LineMaterial>::default(),
         )
-        .insert_resource(PlanetBuilderResource {
-            builder: PlanetBuilder::new(0),
-        })
-        .insert_resource(TexturePlaneEntityResource {
-            entity: None,
-        })
-        .insert_resource(Contours{
-            contours: None,
-        })
+        .init_resource::<ActivePlanet>()
+        .init_resource::<MapLegend>()
+        .init_resource::<PlanetMaterials>()
+        .add_event::<PlanetListEvent>()
+        .add_event::<TerrainEditEvent>()
+        .add_event::<TerrainHistoryEvent>()
+        .add_event::<PlanetFileEvent>()
+        .init_resource::<BrushStroke>()
         .add_systems(Startup, spawn_planet_root_system)
         .add_systems(PostStartup, rebuild_planet_system)
-        .add_systems(Update, rebuild_planet_system)
-        .add_systems(Update, spawn_planet_mesh_system)
-        .add_systems(Update, spawn_planet_colliders_system)
+        .add_systems(
+            Update,
+            (
+                rebuild_planet_system,
+                planet_file_system,
+                poll_planet_generation_system,
+            )
+                .chain()
+                .before(remarch_planet_system),
+        )
+        .add_systems(Update, planet_list_system)
+        // markers queued after the re-march would be removed with the ones it handled
+        .add_systems(Update, refresh_contours_system.before(remarch_planet_system))
+        .add_systems(
+            Update,
+            (
+                remarch_planet_system,
+                spawn_planet_mesh_system,
+                spawn_planet_colliders_system,
+            )
+                .chain(),
+        )
         .add_systems(Update, update_planet_root_system)
-        .add_systems(Update, modify_image_and_refresh_mesh_system)
-        // .add_systems(Update, update_planet_texture_transform)
         .add_systems(
-            PostStartup,
-            spawn_planet_map_visualiser_system,
+            Update,
+            (
+                mouse_terrain_brush_system,
+                apply_terrain_edits_system,
+                commit_terrain_edits_system,
+                terrain_history_input_system,
+                terrain_history_system,
+            )
+                .chain()
+                .before(remarch_planet_system),
         )
+        .add_systems(Update, draw_brush_stroke_system)
+        // .add_systems(Update, update_planet_texture_transform)
+        .add_systems(Update, spawn_planet_map_visualiser_system)
         .add_systems(Update, update_planet_texture)
         // .add_systems(Update, rremarch);
         .add_systems(Update, refresh_planet_texture);
     }
 }
 
-#[derive(Resource)]
-pub struct PlanetBuilderResource {
-    pub builder: PlanetBuilder,
-}
 #[derive(Resource)]
 pub struct TexturePlanetEntityResource {
     pub entity: Option<Entity>,
 }
-#[derive(Resource)]
-struct TexturePlaneEntityResource {
-    entity: Option<Entity>,
+
+/// Value range and colormap of the active planet's texture view, for the ui
+/// legend. `range` is `None` for views that aren't scalar maps.
+#[derive(Resource, Default)]
+pub struct MapLegend {
+    pub range: Option<(f32, f32)>,
+    pub colormap: Colormap,
 }
 
-#[derive(Resource)]
+/// The planet that the ui panel is currently editing.
+#[derive(Resource, Default)]
+pub struct ActivePlanet {
+    pub entity: Option<Entity>,
+}
+
+#[derive(Event, Debug)]
+pub enum PlanetListEvent {
+    Spawn { ui_state: UiState, position: Vec2 },
+    Despawn { planet: Entity },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainEditMode {
+    Add,
+    Remove,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainHistoryEvent {
+    Undo,
+    Redo,
+}
+
+/// Planet file names are relative to save/planets, without extension.
+#[derive(Event, Debug, Clone)]
+pub enum PlanetFileEvent {
+    Save { name: String },
+    Load { name: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TerrainEditShape {
+    Circle,
+    Square,
+    /// a capsule of `radius` from `pos` to `to`
+    Line { to: Vec3 },
+    /// a closed polygon in world space, `pos` and `radius` are ignored
+    Polygon { points: Vec<Vec3> },
+}
+
+/// Adds or removes rock on every planet the shape overlaps. Any system can
+/// send this, the mouse brush is just one producer. `radius` and `falloff` are
+/// in world units, `falloff` is the width of the soft edge and `strength` (0..1)
+/// how far the terrain moves towards `mode`. Stamps of a mouse `stroke` don't
+/// add up where they overlap, every other edit applies in full.
+#[derive(Event, Debug, Clone)]
+pub struct TerrainEditEvent {
+    pub pos: Vec3,
+    pub radius: f32,
+    pub mode: TerrainEditMode,
+    pub falloff: f32,
+    pub strength: f32,
+    pub shape: TerrainEditShape,
+    pub stroke: bool,
+}
+
+impl TerrainEditMode {
+    /// Image value painted by this mode, the planet image holds 255 where there is rock.
+    fn value(&self) -> u8 {
+        match self {
+            TerrainEditMode::Add => 255,
+            TerrainEditMode::Remove => 0,
+        }
+    }
+}
+
+/// Whether an image pixel is rock rather than space.
+pub fn is_solid(pixel: &Rgba<u8>) -> bool {
+    pixel.0[0] >= 128
+}
+
+/// The parameters a planet was (or is being) generated with.
+#[derive(Component)]
+pub struct PlanetSettings {
+    pub ui_state: UiState,
+}
+
+#[derive(Component)]
 pub struct Contours{
     pub contours: Option<PolyLines>,
 }
 
+/// Contour vertex counts before and after simplification, shown in the ui.
+#[derive(Component, Default)]
+pub struct ContourStats {
+    pub raw_vertices: usize,
+    pub render_vertices: usize,
+    pub collider_vertices: usize,
+}
+
+/// The texture plane child, its image and material are reused for every refresh.
+#[derive(Component)]
+struct PlanetTexturePlane {
+    entity: Entity,
+    image: Handle<Image>,
+    material: Handle<StandardMaterial>,
+}
+
+/// Materials shared by every planet's outline and fill.
+#[derive(Resource)]
+struct PlanetMaterials {
+    line: Handle<LineMaterial>,
+    fill: Handle<StandardMaterial>,
+}
+
+impl FromWorld for PlanetMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let line = world
+            .resource_mut::<Assets<LineMaterial>>()
+            .add(LineMaterial {
+                // color: Color::GREEN,
+                color: Color::rgb(1.0, 5.0, 3.0),
+            });
+        let fill = world
+            .resource_mut::<Assets<StandardMaterial>>()
+            .add(StandardMaterial {
+                base_color: Color::rgb(0.12, 0.12, 0.14),
+                unlit: true,
+                ..default()
+            });
+
+        Self { line, fill }
+    }
+}
+
+/// The image changed and the contours must be marched again. `region` is the
+/// dirty pixel rect, `None` re-marches the whole image.
 #[derive(Component)]
-struct NeedsMeshUpdate;
+struct NeedsRemarch {
+    region: Option<IRect>,
+}
 
+/// `region` is the pixel rect whose contours were replaced, `None` means all of them.
 #[derive(Component)]
-struct NeedsColliderUpdate;
+struct NeedsMeshUpdate {
+    region: Option<IRect>,
+}
 
+/// `region` is the pixel rect whose contours were replaced, `None` means all of them.
 #[derive(Component)]
-struct NeedsTextureUpdate;
+struct NeedsColliderUpdate {
+    region: Option<IRect>,
+}
 
 #[derive(Component)]
-struct PlanetColliderTag;
+struct NeedsTextureUpdate;
+
+/// Collider chunk entities of a planet, kept stable while they hold segments.
+#[derive(Component, Default)]
+struct ColliderChunks {
+    chunks: HashMap<IVec2, Entity>,
+    /// collider vertices per chunk, for the stats
+    vertices: HashMap<IVec2, usize>,
+}
+
+/// Outline mesh chunk children of a planet, rebuilt only where contours changed.
+#[derive(Component, Default)]
+struct OutlineChunks {
+    chunks: HashMap<IVec2, OutlineChunk>,
+}
+
+struct OutlineChunk {
+    entity: Entity,
+    mesh: Handle<Mesh>,
+    vertices: usize,
+}
 
 #[derive(Component)]
 pub struct BevyPlanet {
     pub planet_data: Option<PlanetData>,
 }
 
+/// Tiles whose rock was changed by hand since the planet was generated. The
+/// brush only paints the image, so the tile map is stale there.
+#[derive(Component, Default)]
+pub struct EditedTiles {
+    pub tiles: HashSet<UVec2>,
+}
+
+impl EditedTiles {
+    /// Marks every tile a changed pixel rect overlaps.
+    fn mark(&mut self, space: &PlanetSpace, rect: IRect) {
+        if rect.is_empty() {
+            return;
+        }
+        let min = space.pixel_to_tile(rect.min.as_uvec2());
+        let max = space.pixel_to_tile((rect.max - 1).as_uvec2());
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                self.tiles.insert(UVec2::new(x, y));
+            }
+        }
+    }
+}
+
+/// An in-flight planet build running on the async compute pool.
+/// Replacing or removing this component drops the task, which cancels it.
+#[derive(Component)]
+pub struct PlanetGenerationTask {
+    task: Task<Result<PlanetData, String>>,
+}
+
+impl PlanetGenerationTask {
+    fn spawn(state: UiState) -> Self {
+        let thread_pool = AsyncComputeTaskPool::get();
+        let task = thread_pool.spawn(async move { build_planet(&state) });
+
+        Self { task }
+    }
+}
+
+/// Builds a planet on the calling thread.
+pub fn build_planet(state: &UiState) -> Result<PlanetData, String> {
+    let builder = PlanetBuilder::new(0);
+    let options = PlanetOptions::from(state.clone());
+    builder
+        .build(options, state.fractal_noises.iter().collect())
+        .map_err(|err| err.to_string())
+}
+
+/// A planet file whose edits and parameters go on the planet once its
+/// generation task lands and the file restores cleanly.
+#[derive(Component)]
+struct PendingPlanetFile {
+    file: PlanetFile,
+}
+
 #[derive(Component)]
 pub struct PlanetMeshTag;
 
+/// The solid triangulated terrain drawn under the outline.
+#[derive(Component)]
+pub struct PlanetFillTag;
+
 #[derive(Component)]
 pub struct PlanetRootTag;
 
 #[derive(Component)]
 pub struct TexturePlanetRootTag;
 
-fn spawn_planet_root_system(
-    mut commands: Commands,
-    state: ResMut<UiState>,
-) {
-    let planet = None;
-
+fn spawn_planet(
+    commands: &mut Commands,
+    state: &UiState,
+    position: Vec2,
+    name: String,
+) -> Entity {
     let scale = state.scale;
 
     commands
         .spawn(SpatialBundle::from_transform(
-            Transform::from_xyz(0.0, 0.0, 0.0)
+            Transform::from_xyz(position.x, position.y, 0.0)
                 .with_scale(Vec3::new(scale, scale, 1.)),
         ))
-        .insert(Name::new("planet"))
-        .insert(BevyPlanet {
-            planet_data: planet,
+        .insert(Name::new(name))
+        .insert(BevyPlanet { planet_data: None })
+        .insert(PlanetSettings {
+            ui_state: state.clone(),
         })
-        .insert(PlanetRootTag);
+        .insert(Contours { contours: None })
+        .insert(ColliderChunks::default())
+        .insert(OutlineChunks::default())
+        .insert(ContourStats::default())
+        .insert(EditHistory::default())
+        .insert(StrokeCoverage::default())
+        .insert(EditedTiles::default())
+        .insert(PlanetRootTag)
+        .id()
+}
+
+fn spawn_planet_root_system(
+    mut commands: Commands,
+    state: ResMut<UiState>,
+    mut active_planet: ResMut<ActivePlanet>,
+) {
+    let planet = spawn_planet(
+        &mut commands,
+        &state,
+        Vec2::ZERO,
+        "planet".to_string(),
+    );
+    active_planet.entity = Some(planet);
+}
+
+fn planet_list_system(
+    mut commands: Commands,
+    mut events: EventReader<PlanetListEvent>,
+    mut active_planet: ResMut<ActivePlanet>,
+    planet_query: Query<Entity, With<PlanetRootTag>>,
+) {
+    for event in events.read() {
+        match event {
+            PlanetListEvent::Spawn { ui_state, position } => {
+                let name =
+                    format!("planet {}", planet_query.iter().count() + 1);
+                let planet = spawn_planet(
+                    &mut commands,
+                    ui_state,
+                    *position,
+                    name,
+                );
+                commands
+                    .entity(planet)
+                    .insert(PlanetGenerationTask::spawn(ui_state.clone()));
+                active_planet.entity = Some(planet);
+            }
+            PlanetListEvent::Despawn { planet } => {
+                commands.entity(*planet).despawn_recursive();
+                if active_planet.entity == Some(*planet) {
+                    active_planet.entity =
+                        planet_query.iter().find(|p| p != planet);
+                }
+            }
+        }
+    }
 }
 
 fn update_planet_root_system(
-    mut query: Query<&mut Transform, With<PlanetRootTag>>,
-    state: Res<UiState>,
-    mut events: EventReader<GeneralUpdateEvent>,
-    mut vis_query: Query<
-        &mut Visibility,
+    mut query: Query<
+        (Entity, &mut Transform, &mut Visibility, &mut PlanetSettings),
         With<PlanetRootTag>,
     >,
+    state: Res<UiState>,
+    active_planet: Res<ActivePlanet>,
+    mut events: EventReader<GeneralUpdateEvent>,
 ) {
     let ui_event = events.read();
     for _event in ui_event {
-        let mut vis = vis_query.single_mut();
-
-        match state.show_vectors {
-            true => {
-                *vis = Visibility::Visible;
+        for (entity, mut transform, mut vis, mut settings) in
+            query.iter_mut()
+        {
+            match state.show_vectors {
+                true => {
+                    *vis = Visibility::Visible;
+                }
+                false => {
+                    *vis = Visibility::Hidden;
+                }
             }
-            false => {
-                *vis = Visibility::Hidden;
+
+            // the ui only edits the active planet, the others keep their own scale
+            if active_planet.entity == Some(entity) {
+                settings.ui_state.scale = state.scale;
             }
-        }
 
-        if let Ok(mut transform) = query.get_single_mut() {
-            transform.scale =
-                Vec3::new(state.scale, state.scale, 1.);
+            let scale = settings.ui_state.scale;
+            transform.scale = Vec3::new(scale, scale, 1.);
         }
     }
 }
 
 fn rebuild_planet_system(
     mut cmd: Commands,
-    builder_resource: ResMut<PlanetBuilderResource>,
     mut events: EventReader<RegeneratePlanetEvent>,
-    mut planet_query: Query<
-        (Entity, &mut BevyPlanet),
-        With<Name>,
-    >,
+    active_planet: Res<ActivePlanet>,
+    mut planet_query: Query<&mut PlanetSettings>,
 ) {
-    if events.is_empty() {
+    // only the most recent request matters, anything older is stale
+    let Some(event) = events.read().last() else {
         return;
+    };
+
+    let Some(entity) = active_planet.entity else {
+        return;
+    };
+
+    if let Ok(mut settings) = planet_query.get_mut(entity) {
+        settings.ui_state = event.ui_state.clone();
+        // a newer build replaces a planet file that was still loading
+        cmd.entity(entity).remove::<PendingPlanetFile>();
+        // the previous planet_data stays on screen until this task completes
+        cmd.entity(entity).insert(PlanetGenerationTask::spawn(
+            event.ui_state.clone(),
+        ));
     }
+}
 
-    let ui_event = events.read();
-    for event in ui_event {
-        let state = event.ui_state.clone();
-        let options =
-            PlanetOptions::from(event.ui_state.clone());
-        let planet_data = builder_resource.builder.build(
-            options,
-            state.fractal_noises.iter().collect(),
-        );
-        match planet_data {
-            Ok(planet) => {
-                if let Ok((entity, mut bevy_planet)) =
-                    planet_query.get_single_mut()
-                {
-                    bevy_planet.planet_data = Some(planet);
-                    cmd.entity(entity)
-                        .insert(NeedsMeshUpdate);
-                    cmd.entity(entity)
-                        .insert(NeedsColliderUpdate);
-                    cmd.entity(entity)
-                        .insert(NeedsTextureUpdate);
+fn poll_planet_generation_system(
+    mut cmd: Commands,
+    mut state: ResMut<UiState>,
+    active_planet: Res<ActivePlanet>,
+    mut planet_query: Query<(
+        Entity,
+        &mut BevyPlanet,
+        &mut PlanetSettings,
+        &mut PlanetGenerationTask,
+        Option<&PendingPlanetFile>,
+    )>,
+    mut general_update_event_writer: EventWriter<GeneralUpdateEvent>,
+) {
+    for (entity, mut bevy_planet, mut settings, mut generation, planet_file) in
+        planet_query.iter_mut()
+    {
+        let Some(result) =
+            block_on(future::poll_once(&mut generation.task))
+        else {
+            continue;
+        };
+
+        cmd.entity(entity).remove::<PlanetGenerationTask>();
+
+        cmd.entity(entity).remove::<PendingPlanetFile>();
+
+        match result {
+            Ok(mut planet) => {
+                if let Some(PendingPlanetFile { file }) = planet_file {
+                    // keep the planet we have rather than show one that
+                    // doesn't match the file
+                    if let Err(err) = file.restore(&mut planet) {
+                        tracing::error!("error restoring planet file: {}", err);
+                        continue;
+                    }
+                    // the planet only takes the file's parameters once it shows the file
+                    settings.ui_state = file.ui_state.clone();
+                    if active_planet.entity == Some(entity) {
+                        *state = state.with_planet_parameters(&file.ui_state);
+                        state.save().ok();
+                        general_update_event_writer.send(GeneralUpdateEvent {});
+                    }
                 }
+                bevy_planet.planet_data = Some(planet);
+                // the old strokes don't apply to a new image
+                cmd.entity(entity).insert(EditHistory::default());
+                cmd.entity(entity).insert(StrokeCoverage::default());
+                cmd.entity(entity).insert(EditedTiles {
+                    tiles: planet_file
+                        .map(|pending| pending.file.edited_tiles())
+                        .unwrap_or_default(),
+                });
+                cmd.entity(entity)
+                    .insert(NeedsRemarch { region: None });
+                cmd.entity(entity)
+                    .insert(NeedsTextureUpdate);
             }
             Err(err) => {
                 tracing::error!(
                     "error building planet: {}",
                     err
                 );
-                if let Ok((_entity, mut bevy_planet)) =
-                    planet_query.get_single_mut()
-                {
-                    bevy_planet.planet_data = None;
-                }
+                bevy_planet.planet_data = None;
             }
         }
     }
-    events.clear();
 }
 
-fn modify_image_and_refresh_mesh_system(
+/// Saves the active planet to, or loads it from, a planet file.
+fn planet_file_system(
     mut cmd: Commands,
-    mut planet_query: Query<
-        (Entity, &mut BevyPlanet),
-        With<Name>,
-    >,
-    mut events: EventReader<MouseClickWorldEvent>,
-    ui_state: Res<UiState>,
-    
+    mut events: EventReader<PlanetFileEvent>,
+    active_planet: Res<ActivePlanet>,
+    planet_query: Query<(&BevyPlanet, &PlanetSettings, &EditedTiles)>,
 ) {
-    
+    let Some(entity) = active_planet.entity else {
+        return;
+    };
+
     for event in events.read() {
-        
-        if let Ok((entity, mut bevy_planet)) =
-            planet_query.get_single_mut()
-        {
-            if let Some(d) = &mut bevy_planet.planet_data {
-                // d.image.fill(255);
-                let s = ui_state.scale;
-                let r = ui_state.resolution;
+        let Ok((bevy_planet, settings, edited)) = planet_query.get(entity) else {
+            continue;
+        };
+
+        match event {
+            PlanetFileEvent::Save { name } => {
+                let Some(planet) = bevy_planet.planet_data.as_ref() else {
+                    continue;
+                };
+                match PlanetFile::new(&settings.ui_state, planet, &edited.tiles)
+                    .and_then(|file| file.save(name))
+                {
+                    Ok(path) => tracing::info!("saved planet to {}", path.display()),
+                    Err(err) => tracing::error!("error saving planet: {}", err),
+                }
+            }
+            PlanetFileEvent::Load { name } => {
+                let file = match PlanetFile::load(name) {
+                    Ok(file) => file,
+                    Err(err) => {
+                        tracing::error!("error loading planet {}: {}", name, err);
+                        continue;
+                    }
+                };
 
-                let mut scaled_pos = event.pos /s /2.;
-                scaled_pos += Vec3::new(0.5, 0.5,0.);
-                scaled_pos *= r as f32;
-                let x = scaled_pos.x as i32;
-                let y = (r as f32 - scaled_pos.y) as i32;
+                cmd.entity(entity)
+                    .insert(PlanetGenerationTask::spawn(file.ui_state.clone()))
+                    .insert(PendingPlanetFile { file });
+            }
+        }
+    }
+}
 
-                let brush_radius = (0.006 * r as f32 * (ui_state.brush_size * 2.)) as i32;
+// minimum width of the brush's soft edge, in image pixels
+const BRUSH_FALLOFF_PX: f32 = 3.;
 
+/// The mouse stroke in progress.
+#[derive(Resource, Default)]
+struct BrushStroke {
+    button: Option<MouseButton>,
+    last: Option<Vec3>,
+    /// every cursor sample since the button went down, for line and lasso brushes
+    points: Vec<Vec3>,
+}
 
+/// World radius and falloff of the mouse brush. The brush is sized in pixels of
+/// the planet under `pos`.
+fn brush_world_size(
+    planet_query: &Query<(&BevyPlanet, &GlobalTransform), With<Name>>,
+    ui_state: &UiState,
+    pos: Vec3,
+) -> Option<(f32, f32)> {
+    let space = planet_query
+        .iter()
+        .filter_map(|(planet, transform)| {
+            planet
+                .planet_data
+                .as_ref()
+                .map(|d| PlanetSpace::from_planet(transform, d))
+        })
+        .find(|space| space.world_to_local_on_planet(pos).is_some())?;
 
+    let brush_radius = 0.006 * space.dimension() as f32 * (ui_state.brush_size * 2.);
+    let falloff = (brush_radius * ui_state.brush_softness).max(BRUSH_FALLOFF_PX);
 
-                match event.button {
-                    MouseButton::Left => {
-                        paint(&mut d.image, x, y, brush_radius, 0);
-                        cmd.entity(entity).insert(NeedsMeshUpdate);
-                        cmd.entity(entity).insert(NeedsColliderUpdate);
-                        cmd.entity(entity).insert(NeedsTextureUpdate);
-                    }
-                    MouseButton::Right => {
-                        paint(&mut d.image, x, y, brush_radius, 255);
-                        cmd.entity(entity).insert(NeedsMeshUpdate);
-                        cmd.entity(entity).insert(NeedsColliderUpdate);
-                        cmd.entity(entity).insert(NeedsTextureUpdate);
+    Some((
+        space.pixels_to_world_length(brush_radius),
+        space.pixels_to_world_length(falloff),
+    ))
+}
+
+/// Turns mouse painting into terrain edits, left removes rock and right adds it.
+/// Circle and square brushes stamp along the path between cursor samples, line
+/// and lasso brushes are applied once the button is released.
+fn mouse_terrain_brush_system(
+    planet_query: Query<(&BevyPlanet, &GlobalTransform), With<Name>>,
+    mut events: EventReader<MouseClickWorldEvent>,
+    mut edit_events: EventWriter<TerrainEditEvent>,
+    mut stroke: ResMut<BrushStroke>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    ui_state: Res<UiState>,
+) {
+    let button_mode = |button: MouseButton| match button {
+        MouseButton::Left => Some(TerrainEditMode::Remove),
+        MouseButton::Right => Some(TerrainEditMode::Add),
+        _ => None,
+    };
+
+    for event in events.read() {
+        // one stroke at a time
+        if stroke.button.is_some_and(|button| button != event.button) {
+            continue;
+        }
+        let Some(mode) = button_mode(event.button) else {
+            continue;
+        };
+        stroke.button = Some(event.button);
+
+        if let Some((radius, falloff)) = brush_world_size(&planet_query, &ui_state, event.pos) {
+            let from = stroke.last.unwrap_or(event.pos);
+            let stamp = |pos: Vec3, shape: TerrainEditShape| TerrainEditEvent {
+                pos,
+                radius,
+                mode,
+                falloff,
+                strength: ui_state.brush_strength,
+                shape,
+                stroke: true,
+            };
+            match ui_state.brush_shape {
+                // the capsule swept since the last cursor position, in one go
+                BrushShape::Circle => {
+                    edit_events.send(stamp(from, TerrainEditShape::Line { to: event.pos }));
+                }
+                // stamp every quarter radius so fast strokes leave no gaps
+                BrushShape::Square => {
+                    let steps = (from.distance(event.pos) / (radius * 0.25).max(f32::EPSILON))
+                        .ceil()
+                        .max(1.) as usize;
+                    for i in 1..=steps {
+                        let pos = from.lerp(event.pos, i as f32 / steps as f32);
+                        edit_events.send(stamp(pos, TerrainEditShape::Square));
                     }
-                    _ => {}
                 }
+                BrushShape::Line | BrushShape::Lasso => {}
+            }
+        }
+
+        stroke.last = Some(event.pos);
+        stroke.points.push(event.pos);
+    }
+
+    let Some(button) = stroke.button else {
+        return;
+    };
+    if buttons.pressed(button) {
+        return;
+    }
+
+    if let (Some(mode), Some(&first), Some(&last)) =
+        (button_mode(button), stroke.points.first(), stroke.points.last())
+    {
+        let shape = match ui_state.brush_shape {
+            BrushShape::Line => Some(TerrainEditShape::Line { to: last }),
+            BrushShape::Lasso => Some(TerrainEditShape::Polygon {
+                points: stroke.points.clone(),
+            }),
+            BrushShape::Circle | BrushShape::Square => None,
+        };
+        if let (Some(shape), Some((radius, falloff))) =
+            (shape, brush_world_size(&planet_query, &ui_state, first))
+        {
+            edit_events.send(TerrainEditEvent {
+                pos: first,
+                radius,
+                mode,
+                falloff,
+                strength: ui_state.brush_strength,
+                shape,
+                stroke: true,
+            });
+        }
+    }
+
+    *stroke = BrushStroke::default();
+}
+
+/// Previews line and lasso strokes until the button is released.
+fn draw_brush_stroke_system(
+    mut gizmos: Gizmos,
+    stroke: Res<BrushStroke>,
+    ui_state: Res<UiState>,
+) {
+    match ui_state.brush_shape {
+        BrushShape::Line => {
+            if let (Some(first), Some(last)) = (stroke.points.first(), stroke.points.last()) {
+                gizmos.line(*first, *last, Color::WHITE);
             }
         }
+        BrushShape::Lasso => {
+            if let Some(first) = stroke.points.first() {
+                gizmos.linestrip(
+                    stroke.points.iter().chain(std::iter::once(first)).copied(),
+                    Color::WHITE,
+                );
+            }
+        }
+        BrushShape::Circle | BrushShape::Square => {}
     }
 }
 
-fn refresh_planet_texture(
+fn apply_terrain_edits_system(
     mut cmd: Commands,
-    mut events: EventReader<GeneralUpdateEvent>,
     mut planet_query: Query<
-        (Entity, &mut BevyPlanet),
+        (
+            Entity,
+            &mut BevyPlanet,
+            &GlobalTransform,
+            &mut EditHistory,
+            &mut StrokeCoverage,
+            &mut EditedTiles,
+            Option<&NeedsRemarch>,
+        ),
         With<Name>,
     >,
+    mut events: EventReader<TerrainEditEvent>,
 ) {
+    let mut dirty: HashMap<Entity, IRect> = HashMap::new();
+
     for event in events.read() {
-        if let Ok((entity, mut bevy_planet)) =
-            planet_query.get_single_mut()
+        for (entity, mut bevy_planet, planet_transform, mut history, mut coverage, mut edited, _) in
+            planet_query.iter_mut()
         {
+            let Some(d) = &mut bevy_planet.planet_data else {
+                continue;
+            };
+            let space = PlanetSpace::from_planet(planet_transform, d);
+
+            let to_pixel = |pos: Vec3| space.world_to_pixel(pos);
+            let center = to_pixel(event.pos);
+            let radius = space.world_length_to_pixels(event.radius);
+            let falloff = space.world_length_to_pixels(event.falloff);
+
+            let shape = match &event.shape {
+                TerrainEditShape::Circle => PixelShape::Circle { center, radius },
+                TerrainEditShape::Square => PixelShape::Square {
+                    center,
+                    half_size: radius,
+                },
+                TerrainEditShape::Line { to } => PixelShape::Segment {
+                    a: center,
+                    b: to_pixel(*to),
+                    radius,
+                },
+                TerrainEditShape::Polygon { points } => PixelShape::Polygon {
+                    points: points.iter().map(|p| to_pixel(*p)).collect(),
+                },
+            };
+
+            // edits that miss the image paint nothing
+            let Some(bounds) = pixel_bounds(&shape, falloff, d.image.width(), d.image.height())
+            else {
+                continue;
+            };
+            let painted = match event.stroke {
+                true => {
+                    history.record(&d.image, bounds);
+                    paint(
+                        &mut d.image,
+                        &shape,
+                        falloff,
+                        event.strength,
+                        event.mode.value(),
+                        Some(&mut *coverage),
+                    )
+                }
+                // undoing a stroke shouldn't take back an explosion that hit during it
+                false => history.record_step(&mut d.image, bounds, |image| {
+                    paint(image, &shape, falloff, event.strength, event.mode.value(), None)
+                }),
+            };
+            if let Some(rect) = painted {
+                edited.mark(&space, rect);
+                let region = union_region(dirty.get(&entity).copied(), rect);
+                dirty.insert(entity, region);
+            }
+        }
+    }
+
+    for (entity, rect) in dirty {
+        let pending = planet_query
+            .get(entity)
+            .ok()
+            .and_then(|(_, _, _, _, _, _, pending)| pending);
+        mark_image_dirty(&mut cmd, entity, rect, pending);
+    }
+}
+
+/// Queues a re-march and texture refresh for a changed pixel rect.
+fn mark_image_dirty(
+    cmd: &mut Commands,
+    entity: Entity,
+    rect: IRect,
+    pending: Option<&NeedsRemarch>,
+) {
+    // fold into any re-march that hasn't run yet
+    let region = match pending {
+        Some(pending) => pending.region.map(|pending| pending.union(rect)),
+        None => Some(rect),
+    };
+
+    cmd.entity(entity).insert(NeedsRemarch { region });
+    cmd.entity(entity).insert(NeedsTextureUpdate);
+}
+
+/// Closes the planets' undo steps once the mouse stroke is over. Edits sent
+/// by other systems are a step each as they are applied.
+fn commit_terrain_edits_system(
+    stroke: Res<BrushStroke>,
+    mut planet_query: Query<(&BevyPlanet, &mut EditHistory, &mut StrokeCoverage)>,
+) {
+    if stroke.button.is_some() {
+        return;
+    }
+    for (bevy_planet, mut history, mut coverage) in planet_query.iter_mut() {
+        if let Some(d) = &bevy_planet.planet_data {
+            history.commit(&d.image);
+        }
+        coverage.clear();
+    }
+}
+
+fn terrain_history_input_system(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut contexts: EguiContexts,
+    mut events: EventWriter<TerrainHistoryEvent>,
+) {
+    // ctrl+z in a text field edits the text, not the terrain
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if ctrl && keys.just_pressed(KeyCode::KeyZ) {
+        events.send(match shift {
+            true => TerrainHistoryEvent::Redo,
+            false => TerrainHistoryEvent::Undo,
+        });
+    }
+}
+
+/// Undoes or redoes a stroke on the active planet.
+fn terrain_history_system(
+    mut cmd: Commands,
+    mut events: EventReader<TerrainHistoryEvent>,
+    active_planet: Res<ActivePlanet>,
+    mut planet_query: Query<(
+        &mut BevyPlanet,
+        &mut EditHistory,
+        &mut StrokeCoverage,
+        &mut EditedTiles,
+        Option<&NeedsRemarch>,
+    )>,
+) {
+    // touching the planet mutably marks it changed, so only do it for a reason
+    if events.is_empty() {
+        return;
+    }
+    let Some(entity) = active_planet.entity else {
+        return;
+    };
+    let Ok((mut bevy_planet, mut history, mut coverage, mut edited, pending)) =
+        planet_query.get_mut(entity)
+    else {
+        return;
+    };
+    let Some(d) = &mut bevy_planet.planet_data else {
+        return;
+    };
+
+    let mut dirty: Option<IRect> = None;
+    for event in events.read() {
+        // undo closes the stroke in progress, carry on from the restored image
+        coverage.clear();
+        let changed = match event {
+            TerrainHistoryEvent::Undo => history.undo(&mut d.image),
+            TerrainHistoryEvent::Redo => history.redo(&mut d.image),
+        };
+        if let Some(rect) = changed {
+            dirty = Some(union_region(dirty, rect));
+        }
+    }
+
+    if let Some(rect) = dirty {
+        edited.mark(&PlanetSpace::local(d), rect);
+        mark_image_dirty(&mut cmd, entity, rect, pending);
+    }
+}
+
+fn refresh_planet_texture(
+    mut cmd: Commands,
+    mut events: EventReader<GeneralUpdateEvent>,
+    planet_query: Query<Entity, With<BevyPlanet>>,
+) {
+    for _event in events.read() {
+        for entity in planet_query.iter() {
             // cmd.entity(entity).insert(NeedsMeshUpdate);
             // cmd.entity(entity).insert(NeedsColliderUpdate);
             cmd.entity(entity).insert(NeedsTextureUpdate);
@@ -265,240 +965,475 @@ fn refresh_planet_texture(
     }
 }
 
-fn spawn_planet_mesh_system(
-    planet_query: Query<
-        (&BevyPlanet, &NeedsMeshUpdate),
-        With<Name>,
-    >,
+fn remarch_planet_system(
     mut cmd: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut line_materials: ResMut<Assets<LineMaterial>>,
-    mut mesh_query: Query<(Entity, &mut PlanetMeshTag)>,
-    mut planet_root_query: Query<(
+    mut planet_query: Query<(
         Entity,
-        &mut PlanetRootTag,
-    
+        &BevyPlanet,
+        &mut Contours,
+        &NeedsRemarch,
+        Option<&NeedsMeshUpdate>,
+        Option<&NeedsColliderUpdate>,
     )>,
-    mut contours: ResMut<Contours>,
 ) {
-    for (bevy_planet, _) in planet_query.iter() {
-        //todo separate respawning from refreshing mesh
+    for (entity, bevy_planet, mut contours, needs_remarch, pending_mesh, pending_colliders) in
+        planet_query.iter_mut()
+    {
+        let Some(planet) = bevy_planet.planet_data.as_ref() else {
+            continue;
+        };
 
-        if let Some(planet) =
-            bevy_planet.planet_data.as_ref()
-        {
-            for (mesh_entity, _) in mesh_query.iter_mut() {
-                cmd.entity(mesh_entity).despawn();
+        cmd.entity(entity).remove::<NeedsRemarch>();
+
+        let result = match (needs_remarch.region, contours.contours.as_mut()) {
+            // only the neighbourhood of the edit is marched again
+            (Some(dirty), Some(lines)) => {
+                remarch_region(&planet.image, lines, dirty).map(Some)
+            }
+            _ => march_squares_rgba(&planet.image)
+                .map(|lines| {
+                    contours.contours = Some(lines);
+                    None
+                })
+                .map_err(|err| anyhow::anyhow!("{}", err)),
+        };
+
+        let replaced = match result {
+            Ok(replaced) => replaced,
+            Err(err) => {
+                tracing::error!("error marching planet: {}", err);
+                continue;
             }
+        };
 
-            let lines_result =
-                march_squares_rgba(&planet.image);
-
-            
-
-            if let Ok(liness) = (lines_result) {
-
-                contours.contours = Some(liness);
-
-                let lines = flatten_and_zip(contours.contours.as_ref().unwrap());
-                let m = meshes
-                    .add(LineList { vertices: lines });
-
-                let mesh_child = cmd
-                    .spawn(MaterialMeshBundle {
-                        mesh: m,
-                        transform: Transform::from_xyz(
-                            0.0, 0.0, 0.0,
-                        )
-                        .with_scale(Vec3::new(1., 1., 1.)),
-                        material: line_materials.add(
-                            LineMaterial {
-                                // color: Color::GREEN,
-                                color: Color::rgb(
-                                    1.0, 5.0, 3.0,
-                                ),
-                            },
-                        ),
-                        ..Default::default()
-                    })
-                    .insert(PlanetMeshTag)
-                    .id();
+        // mesh and colliders that haven't caught up with an earlier re-march yet
+        let merge = |pending: Option<Option<IRect>>| match (pending, replaced) {
+            (Some(pending), Some(replaced)) => {
+                pending.map(|pending| pending.union(replaced))
+            }
+            (_, replaced) => replaced,
+        };
+
+        cmd.entity(entity).insert(NeedsMeshUpdate {
+            region: merge(pending_mesh.map(|pending| pending.region)),
+        });
+        cmd.entity(entity).insert(NeedsColliderUpdate {
+            region: merge(pending_colliders.map(|pending| pending.region)),
+        });
+    }
+}
 
-                if let Ok((entity, _)) =
-                    planet_root_query.get_single_mut()
-                {
-                    cmd.entity(entity)
-                        .push_children(&[mesh_child]);
-                    cmd.entity(entity)
-                        .remove::<NeedsMeshUpdate>();
+/// Simplification tolerances changed, rebuild the mesh and colliders from the
+/// contours we already have.
+fn refresh_contours_system(
+    mut cmd: Commands,
+    mut events: EventReader<ContoursChangedEvent>,
+    planet_query: Query<Entity, With<Contours>>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+    for entity in planet_query.iter() {
+        cmd.entity(entity).insert(NeedsMeshUpdate { region: None });
+        cmd.entity(entity)
+            .insert(NeedsColliderUpdate { region: None });
+    }
+}
+
+/// Chunks whose smoothed contours can differ after re-marching a pixel rect,
+/// each smoothing pass pulls in vertices from about a pixel further out.
+fn dirty_chunks(
+    region: Option<IRect>,
+    smoothing_iterations: u32,
+    dimension: u32,
+) -> Option<HashSet<IVec2>> {
+    region.map(|region| {
+        let reach = 2 * smoothing_iterations as i32 + 1;
+        chunks_in_pixel_rect(region.inset(reach), dimension)
+    })
+}
+
+fn spawn_planet_mesh_system(
+    state: Res<UiState>,
+    planet_materials: Res<PlanetMaterials>,
+    mut planet_query: Query<
+        (
+            Entity,
+            &BevyPlanet,
+            &Contours,
+            &mut OutlineChunks,
+            &mut ContourStats,
+            &NeedsMeshUpdate,
+        ),
+        With<Name>,
+    >,
+    mut cmd: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    fill_query: Query<(Entity, &Parent, &Handle<Mesh>), With<PlanetFillTag>>,
+) {
+    for (planet_entity, planet, contours, mut outline, mut stats, needs_update) in
+        planet_query.iter_mut()
+    {
+        let (Some(planet), Some(lines)) =
+            (planet.planet_data.as_ref(), contours.contours.as_ref())
+        else {
+            continue;
+        };
+        let dimension = planet.get_dimension() as u32;
+
+        let smoothed = smooth_polylines(lines, state.smoothing_iterations);
+
+        // the outline is simplified chunk by chunk, so a stroke only rebuilds
+        // the chunks around it and the rest keep their mesh
+        let dirty = dirty_chunks(needs_update.region, state.smoothing_iterations, dimension);
+        let mut pieces = chunk_polylines(&smoothed, dirty.as_ref());
+        let coords: HashSet<IVec2> = match dirty {
+            Some(dirty) => dirty,
+            None => outline
+                .chunks
+                .keys()
+                .chain(pieces.keys())
+                .copied()
+                .collect(),
+        };
+
+        let mut childs = Vec::new();
+        for coord in coords {
+            let simplified = pieces
+                .remove(&coord)
+                .map(|lines| simplify_polylines(&lines, state.render_tolerance, dimension));
+
+            match (outline.chunks.get_mut(&coord), simplified) {
+                (Some(chunk), Some(lines)) => {
+                    chunk.vertices = vertex_count(&lines);
+                    meshes.insert(
+                        chunk.mesh.id(),
+                        LineList {
+                            vertices: flatten_and_zip(&lines),
+                        }
+                        .into(),
+                    );
+                }
+                (Some(chunk), None) => {
+                    cmd.entity(chunk.entity).despawn();
+                    outline.chunks.remove(&coord);
                 }
+                (None, Some(lines)) => {
+                    let mesh = meshes.add(Mesh::from(LineList {
+                        vertices: flatten_and_zip(&lines),
+                    }));
+                    let entity = cmd
+                        .spawn(MaterialMeshBundle {
+                            mesh: mesh.clone(),
+                            material: planet_materials.line.clone(),
+                            ..Default::default()
+                        })
+                        .insert(PlanetMeshTag)
+                        .id();
+                    outline.chunks.insert(
+                        coord,
+                        OutlineChunk {
+                            entity,
+                            mesh,
+                            vertices: vertex_count(&lines),
+                        },
+                    );
+                    childs.push(entity);
+                }
+                (None, None) => {}
             }
         }
+        cmd.entity(planet_entity).push_children(&childs);
+
+        stats.raw_vertices = vertex_count(lines);
+        stats.render_vertices = outline.chunks.values().map(|chunk| chunk.vertices).sum();
+
+        // existing fill keeps its entity and handle, only the mesh is swapped
+        let fill = fill_query
+            .iter()
+            .find(|(_, parent, _)| parent.get() == planet_entity);
+
+        let fill_mesh = match state.show_fill {
+            true => contour_fill_mesh(&simplify_polylines(
+                &smoothed,
+                state.render_tolerance,
+                dimension,
+            ))
+            .map_err(|err| {
+                tracing::error!("error filling planet contours: {}", err);
+            })
+            .ok(),
+            false => None,
+        };
+
+        match (fill_mesh, fill) {
+            (Some(fill_mesh), Some((_, _, handle))) => {
+                meshes.insert(handle.id(), fill_mesh);
+            }
+            (Some(fill_mesh), None) => {
+                let fill_child = cmd
+                    .spawn(PbrBundle {
+                        mesh: meshes.add(fill_mesh),
+                        material: planet_materials.fill.clone(),
+                        // just behind the outline
+                        transform: Transform::from_xyz(0.0, 0.0, -0.01),
+                        ..default()
+                    })
+                    .insert(PlanetFillTag)
+                    .id();
+                cmd.entity(planet_entity)
+                    .push_children(&[fill_child]);
+            }
+            (None, Some((fill_entity, _, _))) => {
+                cmd.entity(fill_entity).despawn();
+            }
+            (None, None) => {}
+        }
+
+        cmd.entity(planet_entity)
+            .remove::<NeedsMeshUpdate>();
     }
 }
 
 fn spawn_planet_colliders_system(
+    state: Res<UiState>,
     mut commands: Commands,
-    planet_query: Query<(
+    mut planet_query: Query<(
         Entity,
         &BevyPlanet,
+        &Contours,
+        &mut ColliderChunks,
+        &mut ContourStats,
         &NeedsColliderUpdate,
     )>,
-    collider_query: Query<Entity, With<PlanetColliderTag>>,
-    polylines: Res<Contours>,
 ) {
-    for (planet_entity, planet, _needs_update) in
-        planet_query.iter()
+    for (planet_entity, planet, polylines, mut chunks, mut stats, needs_update) in
+        planet_query.iter_mut()
     {
-        if let Some(planet) = planet.planet_data.as_ref() {
-            for entity in collider_query.iter() {
-                commands.entity(entity).despawn();
+        let (Some(planet), Some(lines)) =
+            (planet.planet_data.as_ref(), polylines.contours.as_ref())
+        else {
+            continue;
+        };
+
+        let dimension = planet.get_dimension() as u32;
+        let smoothing_iterations = match state.collider_contours {
+            ColliderContours::Raw => 0,
+            ColliderContours::Smoothed => state.smoothing_iterations,
+        };
+        let lines = smooth_polylines(lines, smoothing_iterations);
+
+        // a brush stroke only rebuilds the chunks its region overlaps
+        let dirty: Option<HashSet<IVec2>> = match state.collision_mode {
+            CollisionMode::Polyline => {
+                dirty_chunks(needs_update.region, smoothing_iterations, dimension)
+            }
+            // a simplified edge can stray into the next chunk by up to the tolerance
+            CollisionMode::Solid => {
+                let reach = state.collider_tolerance.ceil() as i32 + 1;
+                let region = needs_update.region.map(|region| region.inset(reach));
+                dirty_chunks(region, smoothing_iterations, dimension)
             }
+        };
+        // (collider, vertex count) per chunk
+        let mut colliders: HashMap<IVec2, (Collider, usize)> = match state.collision_mode {
+            // simplified chunk by chunk, so chunks outside the region still line
+            // up with the ones rebuilt here
+            CollisionMode::Polyline => chunk_polylines(&lines, dirty.as_ref())
+                .into_iter()
+                .map(|(coord, pieces)| {
+                    let pieces = simplify_polylines(&pieces, state.collider_tolerance, dimension);
+                    let vertices = vertex_count(&pieces);
+                    let segments = ChunkSegments::from_polylines(&pieces);
+                    (coord, (get_chunk_collider(segments), vertices))
+                })
+                .collect(),
+            // the contours are clipped to each chunk and triangulated there, so
+            // only the chunks around an edit are triangulated again
+            CollisionMode::Solid => {
+                let lines = simplify_by_chunk(&lines, state.collider_tolerance, dimension);
+                let bounds = contour_bounds(&lines);
+                let image_rect = IRect::new(0, 0, dimension as i32, dimension as i32);
+                dirty
+                    .clone()
+                    .unwrap_or_else(|| chunks_in_pixel_rect(image_rect, dimension))
+                    .into_iter()
+                    .filter_map(|coord| {
+                        let clipped = clip_contours_to_chunk(&lines, &bounds, coord);
+                        if clipped.is_empty() {
+                            return None;
+                        }
+                        match tessellate_contours(&clipped) {
+                            Ok((vertices, triangles)) => {
+                                let collider = get_solid_chunk_collider(vertices, triangles)?;
+                                Some((coord, (collider, vertex_count(&clipped))))
+                            }
+                            Err(err) => {
+                                tracing::error!(
+                                    "error triangulating collider chunk {} {}: {}",
+                                    coord.x,
+                                    coord.y,
+                                    err
+                                );
+                                None
+                            }
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        let coords: HashSet<IVec2> = match dirty {
+            Some(dirty) => dirty,
+            None => chunks
+                .chunks
+                .keys()
+                .chain(colliders.keys())
+                .copied()
+                .collect(),
+        };
+
+        let mut childs = Vec::new();
+
+        for coord in coords {
+            let collider = colliders.remove(&coord);
+            match &collider {
+                Some((_, vertices)) => chunks.vertices.insert(coord, *vertices),
+                None => chunks.vertices.remove(&coord),
+            };
 
-            // let colliders = get_colliders(&planet.polylines);
-            // let colliders = get_colliders(&planet.get_polylines().unwrap());
-
-            // let lines_result =
-            //     march_squares_rgba(&planet.image);
-                
-            if let Some(lines) = polylines.contours.as_ref() {
-                let colliders = get_colliders(&lines);
-
-                let mut childs = Vec::new();
-
-                for collider in colliders {
-                    childs.push(
-                        commands
-                            .spawn(collider)
-                            .insert(TransformBundle::from(
-                                Transform::default(),
-                            ))
-                            .insert(Name::new("Collider"))
-                            .insert(PlanetColliderTag)
-                            .id(),
-                    )
+            match (chunks.chunks.get(&coord), collider) {
+                (Some(&entity), Some((collider, _))) => {
+                    commands.entity(entity).insert(collider);
                 }
-
-                commands
-                    .entity(planet_entity)
-                    .push_children(&childs);
-                commands
-                    .entity(planet_entity)
-                    .remove::<NeedsColliderUpdate>();
+                (Some(&entity), None) => {
+                    commands.entity(entity).despawn();
+                    chunks.chunks.remove(&coord);
+                }
+                (None, Some((collider, _))) => {
+                    let entity = commands
+                        .spawn(collider)
+                        .insert(TransformBundle::from(
+                            Transform::default(),
+                        ))
+                        .insert(Name::new(format!(
+                            "Collider chunk {} {}",
+                            coord.x, coord.y
+                        )))
+                        .insert(TerrainChunk { coord })
+                        .id();
+                    chunks.chunks.insert(coord, entity);
+                    childs.push(entity);
+                }
+                (None, None) => {}
             }
         }
+
+        stats.collider_vertices = chunks.vertices.values().sum();
+
+        commands
+            .entity(planet_entity)
+            .push_children(&childs);
+        commands
+            .entity(planet_entity)
+            .remove::<NeedsColliderUpdate>();
     }
 }
 
-fn get_colliders(vecs: &Vec<Vec<Vec2>>) -> Vec<Collider> {
-    let mut colliders = Vec::new();
-    for vec in vecs {
-        colliders
-            .push(Collider::polyline(vec.clone(), None));
-    }
+fn get_chunk_collider(segments: ChunkSegments) -> Collider {
+    Collider::polyline(segments.vertices, Some(segments.indices))
+}
 
-    return colliders;
+/// Clipping leaves slivers along the chunk edges, their zero area triangles
+/// are dropped. `None` if nothing is left.
+fn get_solid_chunk_collider(vertices: Vec<Vec2>, triangles: Vec<[u32; 3]>) -> Option<Collider> {
+    let triangles: Vec<[u32; 3]> = triangles
+        .into_iter()
+        .filter(|t| {
+            let [a, b, c] = t.map(|i| vertices[i as usize]);
+            (b - a).perp_dot(c - a).abs() > f32::EPSILON
+        })
+        .collect();
+    if triangles.is_empty() {
+        return None;
+    }
+    Some(Collider::trimesh(vertices, triangles))
 }
 
 fn spawn_planet_map_visualiser_system(
-    state: ResMut<UiState>,
-    planet_query: Query<(Entity, &BevyPlanet), With<Name>>,
+    state: Res<UiState>,
+    planet_query: Query<
+        Entity,
+        (With<BevyPlanet>, Without<PlanetTexturePlane>),
+    >,
     mut commands: Commands,
-    mut images: ResMut<Assets<Image>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut texture_plane_entity_resource: ResMut<
-        TexturePlaneEntityResource,
-    >,
-    mut planet_root_query: Query<(
-        Entity,
-        &mut PlanetRootTag,
-    )>,
 ) {
-    let _scale = state.scale;
-
-    for (planet_entity, bevy_planet) in planet_query.iter()
-    {
-        if let Some(planet) =
-            bevy_planet.planet_data.as_ref()
-        {
-            let main_map = &planet.planet_map.main;
-
-            let texture_planet_material: Handle<
-                StandardMaterial,
-            > = materials.add(StandardMaterial {
-                base_color_texture: Some(
-                    images
-                        .add(umap_to_bevy_image(&main_map)),
-                ),
-                unlit: true,
-                ..default()
-            });
-
-            let texture_planet_mesh =
-                meshes.add(Plane3d::default());
-            let texture_planet_bundle: MaterialMeshBundle<
-                StandardMaterial,
-            > = PbrBundle {
-                mesh: texture_planet_mesh,
-                material: texture_planet_material.clone(),
-                transform: Transform::from_xyz(
-                    -1.7, 0.0, -0.2,
-                )
-                .with_rotation(Quat::from_euler(
-                    EulerRot::XYZ,
-                    std::f32::consts::PI / 2.,
-                    0.,
-                    0.,
-                ))
-                .with_scale(Vec3::new(1., 1., 1.)),
-                ..default()
-            };
-
-            let texture_plane_entity = commands
-                .spawn(texture_planet_bundle)
-                .insert(TexturePlanetRootTag)
-                .id();
-            texture_plane_entity_resource.entity =
-                Some(texture_plane_entity);
-
-            if let Ok((entity, _)) =
-                planet_root_query.get_single_mut()
-            {
-                commands
-                    .entity(entity)
-                    .push_children(&[texture_plane_entity]);
-            }
-
-            commands
-                .entity(planet_entity)
-                .remove::<NeedsTextureUpdate>();
-        }
+    // generation runs asynchronously, so the plane is spawned with a blank
+    // image and update_planet_texture fills it in once the first build lands
+    for planet_entity in planet_query.iter() {
+        let texture_planet_image = images.add(Image::default());
+        let texture_planet_material: Handle<
+            StandardMaterial,
+        > = materials.add(StandardMaterial {
+            base_color_texture: Some(texture_planet_image.clone()),
+            unlit: true,
+            ..default()
+        });
+
+        let texture_planet_mesh =
+            meshes.add(Plane3d::default());
+        let texture_planet_bundle: MaterialMeshBundle<
+            StandardMaterial,
+        > = PbrBundle {
+            mesh: texture_planet_mesh,
+            material: texture_planet_material.clone(),
+            transform: PlanetSpace::texture_plane_transform(),
+            visibility: match state.show_texture {
+                true => Visibility::Visible,
+                false => Visibility::Hidden,
+            },
+            ..default()
+        };
+
+        let texture_plane_entity = commands
+            .spawn(texture_planet_bundle)
+            .insert(TexturePlanetRootTag)
+            .id();
+
+        commands
+            .entity(planet_entity)
+            .insert(PlanetTexturePlane {
+                entity: texture_plane_entity,
+                image: texture_planet_image,
+                material: texture_planet_material,
+            })
+            .push_children(&[texture_plane_entity]);
     }
 }
 
 fn update_planet_texture(
     state: ResMut<UiState>,
-    texture_planet_entity_resource: Res<
-        TexturePlaneEntityResource,
-    >,
+    active_planet: Res<ActivePlanet>,
+    mut legend: ResMut<MapLegend>,
     planet_query: Query<
-        (Entity, &BevyPlanet, &NeedsTextureUpdate),
-        With<Name>,
+        (Entity, &BevyPlanet, &PlanetTexturePlane),
+        (With<Name>, With<NeedsTextureUpdate>),
     >,
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut query: Query<&mut Handle<StandardMaterial>>,
     mut vis_query: Query<
         &mut Visibility,
         With<TexturePlanetRootTag>,
     >,
 ) {
-    for (planet_entity, bevy_planet, _) in
+    for (planet_entity, bevy_planet, texture_plane) in
         planet_query.iter()
     {
-        if let Ok(mut vis) = vis_query.get_single_mut() {
+        if let Ok(mut vis) = vis_query.get_mut(texture_plane.entity) {
             match state.show_texture {
                 true => *vis = Visibility::Visible,
                 false => *vis = Visibility::Hidden,
@@ -507,85 +1442,26 @@ fn update_planet_texture(
             if let Some(planet) =
                 bevy_planet.planet_data.as_ref()
             {
-                if let Some(texture_plane_entity) =
-                    texture_planet_entity_resource.entity
-                {
-                    if let Ok(mut material_handle) =
-                        query.get_mut(texture_plane_entity)
-                    {
-                        let new_image = match state.bitmap_dislpay {
-                            BitmapDisplay::PlanetRaw => umap_to_bevy_image(&planet.planet_map.main),
-
-                            // match &planet.planet_map.main {
-                            //     Some(main_map) => umap_to_bevy_image(main_map),
-                            //     None => continue,
-                            // },
-                            BitmapDisplay::PlanetProcessed => {
-                                imagebuffer_to_bevy_image(&planet.image)
-                            }
-
-                            // BitmapDisplay::PlanetProcessed => match &planet.image {
-                            //     Some(image) => imagebuffer_to_bevy_image(image),
-                            //     None => continue,
-                            // },
-                            BitmapDisplay::Altitude => {
-                                fmap_to_bevy_image(&planet.planet_map.altitude)
-                            }
+                if active_planet.entity == Some(planet_entity) {
+                    legend.range = bitmap_display_value_range(
+                        planet,
+                        &state.bitmap_dislpay,
+                        &state.map_style,
+                    );
+                    legend.colormap = state.map_style.colormap;
+                }
 
-                            // BitmapDisplay::Altitude => match &planet.planet_map.altitude {
-                            //     Some(v) => fmap_to_bevy_image(v),
-                            //     None => continue,
-                            // },
-                            BitmapDisplay::Depth => fmap_to_bevy_image(&planet.planet_map.depth),
-
-                            // BitmapDisplay::Depth => match &planet.planet_map.depth {
-                            //     Some(v) => fmap_to_bevy_image(v),
-                            //     None => continue,
-                            // },
-                            BitmapDisplay::Mask => match &planet.planet_map.mask {
-                                Some(v) => fmap_to_bevy_image(v),
-                                None => continue,
-                            },
-                            BitmapDisplay::RoomsRaw => match &planet.planet_map.rooms_raw {
-                                Some(v) => umap_to_bevy_image(v),
-                                None => continue,
-                            },
-                            BitmapDisplay::RoomsDebug => match &planet.roooms {
-                                // Some(rooms) => imagebuffer_to_bevy_image(rooms_debug),
-                                Some(_t) => {
-                                    continue;
-                                    // if let Some(rooms) = t.rooms{
-                                    //     room_vec_to_bevy_image(rooms, planet.get_dimension().unwrap())
-                                    // }
-                                    // else {
-                                    //     continue;
-                                    // }
-                                }
-                                None => continue,
-                            },
-
-                            BitmapDisplay::TileMapDebug => tile_map_to_bevy_image(&planet.tile_map),
-                            // BitmapDisplay::TileMapDebug => match &planet.tile_map {
-                            //     // Some(rooms) => imagebuffer_to_bevy_image(rooms_debug),
-                            //     Some(t) => tile_map_to_bevy_image(t),
-                            //     None => continue,
-                            // },
-                        };
-
-                        let new_image_handle =
-                            images.add(new_image);
-                        let new_material =
-                            StandardMaterial {
-                                base_color_texture: Some(
-                                    new_image_handle,
-                                ),
-                                unlit: true,
-                                ..default()
-                            };
-
-                        *material_handle =
-                            materials.add(new_material);
-                    }
+                if let Some(new_image) = composite_texture_layers(
+                    planet,
+                    &state.bitmap_dislpay,
+                    &state.texture_layers,
+                    &state.map_style,
+                ) {
+                    // render-only images leave `Assets` once uploaded, so the
+                    // new one goes in under the old id rather than through get_mut
+                    images.insert(texture_plane.image.id(), new_image);
+                    // touching the material rebinds it to the new texture
+                    materials.get_mut(&texture_plane.material);
                 }
 
                 commands
@@ -620,49 +1496,3 @@ use image::{ImageBuffer, Rgba};
 //         }
 //     }
 // }
-
-use std::cmp::min;
-
-fn paint(mut image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: i32, y: i32, radius: i32, v: u8) {
-    let (width, height) = image.dimensions();
-
-    // Define the thickness of the antialiasing edge.
-    let aa_edge = 3.0; // The thickness of the antialiasing in pixels.
-
-    for y_coord in 0..height {
-        for x_coord in 0..width {
-            let dx = x_coord as f32 - x as f32;
-            let dy = y_coord as f32 - y as f32;
-            let distance = (dx * dx + dy * dy).sqrt();
-            let radius_f = radius as f32;
-
-            // Check if we're within the antialiasing edge.
-            if distance <= radius_f + aa_edge && distance >= radius_f - aa_edge {
-                let alpha = ((radius_f + aa_edge - distance) / (2.0 * aa_edge)).clamp(0.0, 1.0);
-                let existing_color = image.get_pixel(x_coord, y_coord).0;
-                let blended_color = blend(existing_color, [v, v, v, (255.0 * 0.2 * alpha) as u8]);
-                image.put_pixel(x_coord, y_coord, Rgba(blended_color));
-            } else if distance < radius_f - aa_edge {
-                // Inside the circle, fully opaque
-                image.put_pixel(x_coord, y_coord, Rgba([v, v, v, 255]));
-            }
-        }
-    }
-}
-
-// Blend two colors together based on alpha
-fn blend(color1: [u8; 4], color2: [u8; 4]) -> [u8; 4] {
-    let alpha = color2[3] as f32 / 255.0;
-    let inv_alpha = 1.0 - alpha;
-    [
-        (color1[0] as f32 * inv_alpha + color2[0] as f32 * alpha) as u8,
-        (color1[1] as f32 * inv_alpha + color2[1] as f32 * alpha) as u8,
-        (color1[2] as f32 * inv_alpha + color2[2] as f32 * alpha) as u8,
-        min(255, color1[3] + color2[3]), // Ensure alpha doesn't exceed 255
-    ]
-}
-
-
-
-
-// }
\ No newline at end of file
@@ -1,2 +1,11 @@
 pub mod lib;
-pub mod conversions;
\ No newline at end of file
+pub mod conversions;
+pub mod contours;
+pub mod chunks;
+pub mod fill;
+pub mod brush;
+pub mod history;
+pub mod planet_file;
+pub mod colormap;
+pub mod layers;
+pub mod planet_space;
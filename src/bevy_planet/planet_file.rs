@@ -0,0 +1,125 @@
+use std::{collections::HashSet, env, fs, io::Cursor, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bevy::math::UVec2;
+use image::{ImageBuffer, ImageFormat, ImageOutputFormat, Rgba};
+use planet::{planet_data::PlanetData, tile_map::TileMap};
+use serde::{Deserialize, Serialize};
+
+use crate::ui_state::UiState;
+
+const PLANET_FILE_VERSION: u32 = 1;
+
+/// A hand edited planet. The generation options rebuild the maps the brush
+/// can't touch, the edited image is stored as a base64 png on top of that.
+/// The tile map and rooms are stored whole and put back on load, so rooms,
+/// the mst and paths match the saved rock even if the generator has changed.
+#[derive(Serialize, Deserialize)]
+pub struct PlanetFile {
+    pub version: u32,
+    pub ui_state: UiState,
+    pub image: String,
+    pub tile_map: serde_yaml::Value,
+    pub rooms: serde_yaml::Value,
+    /// tiles the brush changed, where the image is newer than the tile map
+    #[serde(default)]
+    pub edited_tiles: Vec<[u32; 2]>,
+}
+
+impl PlanetFile {
+    pub fn new(
+        ui_state: &UiState,
+        planet: &PlanetData,
+        edited_tiles: &HashSet<UVec2>,
+    ) -> Result<Self> {
+        let mut png = Cursor::new(Vec::new());
+        planet.image.write_to(&mut png, ImageOutputFormat::Png)?;
+
+        Ok(Self {
+            version: PLANET_FILE_VERSION,
+            ui_state: ui_state.clone(),
+            image: STANDARD.encode(png.into_inner()),
+            tile_map: serde_yaml::to_value(&planet.tile_map)?,
+            rooms: serde_yaml::to_value(&planet.roooms)?,
+            edited_tiles: edited_tiles.iter().map(|tile| tile.to_array()).collect(),
+        })
+    }
+
+    pub fn edited_tiles(&self) -> HashSet<UVec2> {
+        self.edited_tiles.iter().map(|tile| UVec2::from_array(*tile)).collect()
+    }
+
+    pub fn decode_image(&self) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+        let png = STANDARD.decode(&self.image)?;
+        Ok(image::load_from_memory_with_format(&png, ImageFormat::Png)?.to_rgba8())
+    }
+
+    pub fn decode_tile_map(&self) -> Result<TileMap> {
+        Ok(serde_yaml::from_value(self.tile_map.clone())?)
+    }
+
+    /// Puts the saved image, tile map and rooms on a planet regenerated from
+    /// `ui_state`. The planet is left as it was if any of them can't be restored.
+    pub fn restore(&self, planet: &mut PlanetData) -> Result<()> {
+        let image = self.decode_image()?;
+        if image.dimensions() != planet.image.dimensions() {
+            return Err(anyhow!(
+                "saved image is {:?}, the regenerated planet {:?}",
+                image.dimensions(),
+                planet.image.dimensions()
+            ));
+        }
+        let tile_map = self.decode_tile_map()?;
+        let rooms = serde_yaml::from_value(self.rooms.clone())?;
+
+        planet.image = image;
+        planet.tile_map = tile_map;
+        planet.roooms = rooms;
+        Ok(())
+    }
+
+    pub fn save(&self, name: &str) -> Result<PathBuf> {
+        let file_path = planet_file_path(name)?;
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file_path, serde_yaml::to_string(self)?)?;
+
+        Ok(file_path)
+    }
+
+    /// Reads a planet file and checks its image and tile map decode, so a
+    /// broken file is refused before anything is regenerated for it.
+    pub fn load(name: &str) -> Result<PlanetFile> {
+        let contents = fs::read_to_string(planet_file_path(name)?)?;
+        let file: PlanetFile = serde_yaml::from_str(&contents)?;
+        if file.version != PLANET_FILE_VERSION {
+            return Err(anyhow!("unsupported planet file version {}", file.version));
+        }
+        file.decode_image()?;
+        file.decode_tile_map()?;
+        Ok(file)
+    }
+}
+
+/// Refuses file names typed in the ui that could point outside the directory
+/// they are saved to. Names are plain file names without extension.
+pub fn check_file_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.contains(['/', '\\', ':'])
+        || name.contains("..")
+        || name.starts_with('.')
+    {
+        return Err(anyhow!("invalid file name {:?}", name));
+    }
+    Ok(())
+}
+
+/// Where a planet file is kept, inside save/planets.
+pub fn planet_file_path(name: &str) -> Result<PathBuf> {
+    check_file_name(name)?;
+    Ok(env::current_dir()?
+        .join("save/planets")
+        .join(format!("{}.yaml", name)))
+}
@@ -0,0 +1,245 @@
+use bevy::{
+    math::{Affine3A, EulerRot, Quat, UVec2, Vec2, Vec3},
+    transform::components::{GlobalTransform, Transform},
+};
+use planet::planet_data::PlanetData;
+
+/// Where the texture plane sits in planet local space, beside the vector planet.
+pub const TEXTURE_PLANE_OFFSET: Vec3 = Vec3::new(-1.7, 0., -0.2);
+
+/// Planet local [-1, 1] space to image pixels, y pointing down.
+pub fn local_to_pixel(p: Vec2, dimension: u32) -> Vec2 {
+    let d = dimension as f32;
+    Vec2::new((p.x / 2. + 0.5) * d, (0.5 - p.y / 2.) * d)
+}
+
+pub fn pixel_to_local(p: Vec2, dimension: u32) -> Vec2 {
+    let d = dimension as f32;
+    Vec2::new(p.x / d * 2. - 1., 1. - p.y / d * 2.)
+}
+
+/// The coordinate spaces of one planet and the conversions between them.
+///
+/// - world: bevy world units, through the planet's transform
+/// - local: [-1, 1] with y up, the contours and colliders live here
+/// - pixel: processed image pixels, [0, dimension) with y down
+/// - tile: tile map cells, [0, tiles) with y down, `tile_map[x][y]`
+///
+/// The texture plane shows the same local space moved by `TEXTURE_PLANE_OFFSET`.
+#[derive(Debug, Clone, Copy)]
+pub struct PlanetSpace {
+    transform: Affine3A,
+    scale: f32,
+    dimension: u32,
+    tiles: u32,
+}
+
+impl PlanetSpace {
+    pub fn new(transform: &GlobalTransform, dimension: u32, tiles: u32) -> Self {
+        Self {
+            transform: transform.affine(),
+            // planets are scaled uniformly in x and y
+            scale: transform.compute_transform().scale.x,
+            dimension,
+            tiles,
+        }
+    }
+
+    pub fn from_planet(transform: &GlobalTransform, planet: &PlanetData) -> Self {
+        Self::new(
+            transform,
+            planet.get_dimension() as u32,
+            planet.tile_map.len() as u32,
+        )
+    }
+
+    /// World and local space are the same, for work that never leaves the planet.
+    pub fn local(planet: &PlanetData) -> Self {
+        Self::from_planet(&GlobalTransform::IDENTITY, planet)
+    }
+
+    pub fn dimension(&self) -> u32 {
+        self.dimension
+    }
+
+    pub fn tiles(&self) -> u32 {
+        self.tiles
+    }
+
+    pub fn world_to_local(&self, pos: Vec3) -> Vec2 {
+        self.transform.inverse().transform_point3(pos).truncate()
+    }
+
+    pub fn local_to_world(&self, p: Vec2) -> Vec3 {
+        self.transform.transform_point3(p.extend(0.))
+    }
+
+    /// `world_to_local`, if the point lies on the planet.
+    pub fn world_to_local_on_planet(&self, pos: Vec3) -> Option<Vec2> {
+        let local = self.world_to_local(pos);
+        (local.x.abs() <= 1. && local.y.abs() <= 1.).then_some(local)
+    }
+
+    pub fn local_to_pixel(&self, p: Vec2) -> Vec2 {
+        local_to_pixel(p, self.dimension)
+    }
+
+    pub fn pixel_to_local(&self, p: Vec2) -> Vec2 {
+        pixel_to_local(p, self.dimension)
+    }
+
+    /// World position to (unbounded) image pixel coordinates.
+    pub fn world_to_pixel(&self, pos: Vec3) -> Vec2 {
+        self.local_to_pixel(self.world_to_local(pos))
+    }
+
+    pub fn pixel_to_world(&self, p: Vec2) -> Vec3 {
+        self.local_to_world(self.pixel_to_local(p))
+    }
+
+    /// The pixel `p` falls in, if it's inside the image.
+    pub fn pixel_in_image(&self, p: Vec2) -> Option<UVec2> {
+        let d = self.dimension as f32;
+        (p.x >= 0. && p.y >= 0. && p.x < d && p.y < d).then(|| p.floor().as_uvec2())
+    }
+
+    pub fn local_to_tile(&self, p: Vec2) -> Option<UVec2> {
+        let t = local_to_pixel(p, self.tiles);
+        let tiles = self.tiles as f32;
+        (t.x >= 0. && t.y >= 0. && t.x < tiles && t.y < tiles).then(|| t.floor().as_uvec2())
+    }
+
+    /// The center of a tile.
+    pub fn tile_to_local(&self, tile: UVec2) -> Vec2 {
+        pixel_to_local(tile.as_vec2() + 0.5, self.tiles)
+    }
+
+    /// The tile an image pixel lies in, the tile map may be coarser than the image.
+    pub fn pixel_to_tile(&self, pixel: UVec2) -> UVec2 {
+        pixel * self.tiles / self.dimension.max(1)
+    }
+
+    pub fn world_to_tile(&self, pos: Vec3) -> Option<UVec2> {
+        self.local_to_tile(self.world_to_local(pos))
+    }
+
+    pub fn tile_to_world(&self, tile: UVec2) -> Vec3 {
+        self.local_to_world(self.tile_to_local(tile))
+    }
+
+    pub fn world_length_to_pixels(&self, length: f32) -> f32 {
+        length / self.scale * self.dimension as f32 / 2.
+    }
+
+    pub fn pixels_to_world_length(&self, pixels: f32) -> f32 {
+        pixels * 2. / self.dimension as f32 * self.scale
+    }
+
+    /// A point of the texture plane, given in local coordinates of the
+    /// planet it shows, to world.
+    pub fn texture_to_world(&self, p: Vec2) -> Vec3 {
+        self.local_to_world(p + TEXTURE_PLANE_OFFSET.truncate())
+    }
+
+    /// The texture plane's transform relative to its planet. The plane mesh
+    /// faces +y with v along +z, tipping it up puts v = 0 at the top like
+    /// image row 0.
+    pub fn texture_plane_transform() -> Transform {
+        Transform::from_translation(TEXTURE_PLANE_OFFSET).with_rotation(Quat::from_euler(
+            EulerRot::XYZ,
+            std::f32::consts::PI / 2.,
+            0.,
+            0.,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMENSION: u32 = 200;
+    const TILES: u32 = 50;
+
+    fn space() -> PlanetSpace {
+        let transform = GlobalTransform::from(
+            Transform::from_xyz(30., -12., 0.).with_scale(Vec3::new(100., 100., 1.)),
+        );
+        PlanetSpace::new(&transform, DIMENSION, TILES)
+    }
+
+    fn close(a: Vec2, b: Vec2) -> bool {
+        a.distance(b) < 1e-3
+    }
+
+    #[test]
+    fn pixel_origin_is_top_left() {
+        let space = space();
+        assert!(close(space.local_to_pixel(Vec2::new(-1., 1.)), Vec2::ZERO));
+        assert!(close(
+            space.local_to_pixel(Vec2::new(1., -1.)),
+            Vec2::splat(DIMENSION as f32)
+        ));
+        assert!(close(
+            space.local_to_pixel(Vec2::ZERO),
+            Vec2::splat(DIMENSION as f32 / 2.)
+        ));
+    }
+
+    #[test]
+    fn local_pixel_round_trip() {
+        let space = space();
+        for p in [Vec2::new(-0.3, 0.8), Vec2::new(0.9, -0.1), Vec2::ZERO] {
+            assert!(close(space.pixel_to_local(space.local_to_pixel(p)), p));
+        }
+    }
+
+    #[test]
+    fn world_round_trip() {
+        let space = space();
+        let pos = Vec3::new(55., 20., 0.);
+        assert!(close(
+            space.pixel_to_world(space.world_to_pixel(pos)).truncate(),
+            pos.truncate()
+        ));
+        assert!(close(space.world_to_local(Vec3::new(30., -12., 0.)), Vec2::ZERO));
+        // world y up is image y down
+        assert!(space.world_to_pixel(Vec3::new(30., 50., 0.)).y < DIMENSION as f32 / 2.);
+    }
+
+    #[test]
+    fn world_lengths_round_trip() {
+        let space = space();
+        assert!((space.world_length_to_pixels(100.) - DIMENSION as f32 / 2.).abs() < 1e-3);
+        assert!((space.pixels_to_world_length(space.world_length_to_pixels(7.)) - 7.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tiles_follow_the_image() {
+        let space = space();
+        assert_eq!(space.local_to_tile(Vec2::new(-0.99, 0.99)), Some(UVec2::ZERO));
+        assert_eq!(
+            space.local_to_tile(Vec2::new(0.99, -0.99)),
+            Some(UVec2::splat(TILES - 1))
+        );
+        assert_eq!(space.local_to_tile(Vec2::new(1.5, 0.)), None);
+
+        let tile = UVec2::new(7, 31);
+        assert_eq!(space.local_to_tile(space.tile_to_local(tile)), Some(tile));
+        assert_eq!(space.pixel_to_tile(UVec2::new(29, 125)), tile);
+        assert_eq!(space.world_to_tile(space.tile_to_world(tile)), Some(tile));
+    }
+
+    #[test]
+    fn texture_plane_matches_pixels() {
+        // the plane mesh corner with uv (0, 0), see PlaneMeshBuilder
+        let corner = PlanetSpace::texture_plane_transform().transform_point(Vec3::new(-1., 0., -1.));
+        let local = (corner - TEXTURE_PLANE_OFFSET).truncate();
+        assert!(close(local_to_pixel(local, DIMENSION), Vec2::ZERO));
+
+        // and uv (1, 1)
+        let corner = PlanetSpace::texture_plane_transform().transform_point(Vec3::new(1., 0., 1.));
+        let local = (corner - TEXTURE_PLANE_OFFSET).truncate();
+        assert!(close(local_to_pixel(local, DIMENSION), Vec2::splat(DIMENSION as f32)));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{player::MyPlayerTag, ui::OccupiedScreenSpace, ui_state::{self, CameraMode}};
+use crate::{player::{GravityCenter, MyPlayerTag}, ui::OccupiedScreenSpace, ui_state::{self, CameraMode}};
 use bevy::{
     core_pipeline::{bloom::BloomSettings, tonemapping::Tonemapping},
     prelude::*,
@@ -82,7 +82,11 @@ fn track_camera(
     }
 }
 
-fn apply_angle(mut q: Query<&mut Transform, With<Camera>>, ui_state: Res<ui_state::UiState>) {
+fn apply_angle(
+    mut q: Query<&mut Transform, With<Camera>>,
+    ui_state: Res<ui_state::UiState>,
+    gravity_center: Res<GravityCenter>,
+) {
     let mut transform = q.single_mut();
 
     match ui_state.camera_mode {
@@ -91,13 +95,13 @@ fn apply_angle(mut q: Query<&mut Transform, With<Camera>>, ui_state: Res<ui_stat
 
                 let distance_to_center = distance_squared(
                     &Vec2::new(transform.translation.x, transform.translation.y),
-                    &Vec2::new(0.0, 0.0),
+                    &gravity_center.center,
                 );
                 let t = (distance_to_center / 100.).clamp(0., 1.);
 
 
                 // let mut transform = q.single_mut();
-                let direction = transform.translation - Vec3::ZERO; // Vec3::ZERO is the origin
+                let direction = transform.translation - gravity_center.center.extend(0.); // the planet currently pulling on the player
                 let angle = f32::atan2(direction.x, direction.y);
                 let target_rotatin = Quat::from_rotation_z(-angle);
                 let current_rotation = transform.rotation;
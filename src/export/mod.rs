@@ -0,0 +1,93 @@
+use std::{env, fs, path::PathBuf};
+
+use anyhow::Result;
+use bevy::{prelude::*, utils::tracing};
+
+use crate::{
+    bevy_planet::{
+        lib::{ActivePlanet, BevyPlanet, Contours, PlanetSettings},
+        planet_file::check_file_name,
+        planet_space::PlanetSpace,
+    },
+    room_report::{spawn_room, RoomReport},
+};
+
+pub mod svg;
+pub mod tiled;
+
+use svg::{write_svg, SvgOptions};
+use tiled::write_tiled;
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportEvent>()
+            .add_systems(Update, export_system);
+    }
+}
+
+/// Exports the active planet to save/exports, `name` is the file name without extension.
+#[derive(Event, Debug, Clone)]
+pub enum ExportEvent {
+    Svg { name: String, options: SvgOptions },
+    Tiled { name: String },
+    /// the room report table
+    RoomsCsv { name: String },
+}
+
+pub fn export_path(name: &str, extension: &str) -> Result<PathBuf> {
+    check_file_name(name)?;
+    Ok(env::current_dir()?
+        .join("save/exports")
+        .join(format!("{}.{}", name, extension)))
+}
+
+fn export_system(
+    mut events: EventReader<ExportEvent>,
+    active_planet: Res<ActivePlanet>,
+    planet_query: Query<(&BevyPlanet, &Contours, &PlanetSettings, &GlobalTransform)>,
+) {
+    for event in events.read() {
+        let Some((planet, contours, settings, transform)) = active_planet
+            .entity
+            .and_then(|entity| planet_query.get(entity).ok())
+        else {
+            continue;
+        };
+        let (Some(planet), Some(contours)) = (&planet.planet_data, &contours.contours) else {
+            tracing::warn!("nothing to export, the planet hasn't been built yet");
+            continue;
+        };
+
+        let result = match event {
+            ExportEvent::Svg { name, options } => {
+                let options = SvgOptions {
+                    // world units follow the planet being exported
+                    world_scale: options.world_scale.map(|_| settings.ui_state.scale),
+                    ..options.clone()
+                };
+                export_path(name, "svg").and_then(|path| {
+                    write_svg(&path, contours, planet, &options).map(|_| path)
+                })
+            }
+            ExportEvent::Tiled { name } => export_path(name, "json")
+                .and_then(|path| write_tiled(&path, planet).map(|_| path)),
+            ExportEvent::RoomsCsv { name } => export_path(name, "csv").and_then(|path| {
+                let space = PlanetSpace::from_planet(transform, planet);
+                let report =
+                    RoomReport::from_planet(planet, spawn_room(planet, &space, &settings.ui_state));
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&path, report.to_csv())?;
+                Ok(path)
+            }),
+        };
+
+        match result {
+            Ok(path) => tracing::info!("exported {}", path.display()),
+            Err(err) => tracing::error!("error exporting planet: {}", err),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::{fmt::Write, fs, path::Path};
+
+use anyhow::Result;
+use glam::{UVec2, Vec2};
+use planet::{planet_data::PlanetData, types::PolyLines};
+
+use crate::bevy_planet::planet_space::PlanetSpace;
+
+/// What goes into an svg besides the contours.
+#[derive(Debug, Clone, Default)]
+pub struct SvgOptions {
+    /// world units for a planet of this scale, planet normalized [-1, 1] if `None`
+    pub world_scale: Option<f32>,
+    pub room_centers: bool,
+    pub mst: bool,
+    pub triangulation: bool,
+}
+
+/// One `<path>` per contour polyline, plus optional room layers, y pointing up
+/// like in the game.
+pub fn planet_to_svg(contours: &PolyLines, planet: &PlanetData, options: &SvgOptions) -> String {
+    let scale = options.world_scale.unwrap_or(1.);
+    // svg y points down
+    let p = |v: Vec2| Vec2::new(v.x * scale, -v.y * scale);
+    let stroke = 0.002 * scale;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        -scale,
+        -scale,
+        2. * scale,
+        2. * scale
+    )
+    .ok();
+
+    writeln!(
+        svg,
+        r#"<g id="contours" fill="none" stroke="black" stroke-width="{}">"#,
+        stroke
+    )
+    .ok();
+    for line in contours.iter().filter(|line| line.len() >= 2) {
+        let mut d = String::new();
+        for (i, v) in line.iter().enumerate() {
+            let v = p(*v);
+            write!(d, "{}{} {} ", if i == 0 { "M" } else { "L" }, v.x, v.y).ok();
+        }
+        writeln!(svg, r#"<path d="{}"/>"#, d.trim_end()).ok();
+    }
+    writeln!(svg, "</g>").ok();
+
+    if let Some(roooms) = &planet.roooms {
+        let space = PlanetSpace::local(planet);
+        let tile = |x, y| p(space.tile_to_local(UVec2::new(x, y)));
+        let center = |i: usize| tile(roooms.rooms[i].center.x as u32, roooms.rooms[i].center.y as u32);
+
+        if options.triangulation {
+            if let Some(triangulation) = roooms.get_triangulation_coords() {
+                writeln!(
+                    svg,
+                    r#"<g id="triangulation" stroke="red" stroke-width="{}">"#,
+                    stroke
+                )
+                .ok();
+                for (a, b) in triangulation.iter() {
+                    let a = tile(a.x as u32, a.y as u32);
+                    let b = tile(b.x as u32, b.y as u32);
+                    write_line(&mut svg, a, b);
+                }
+                writeln!(svg, "</g>").ok();
+            }
+        }
+
+        if options.mst {
+            if let Some(mst) = &roooms.mst {
+                writeln!(
+                    svg,
+                    r#"<g id="mst" stroke="hotpink" stroke-width="{}">"#,
+                    stroke * 2.
+                )
+                .ok();
+                for (a, b) in mst {
+                    write_line(&mut svg, center(*a), center(*b));
+                }
+                writeln!(svg, "</g>").ok();
+            }
+        }
+
+        if options.room_centers {
+            writeln!(svg, r#"<g id="room-centers" fill="red">"#).ok();
+            for (i, room) in roooms.rooms.iter().enumerate() {
+                let c = center(i);
+                writeln!(
+                    svg,
+                    r#"<circle id="room-{}" cx="{}" cy="{}" r="{}"/>"#,
+                    room.id,
+                    c.x,
+                    c.y,
+                    0.01 * scale
+                )
+                .ok();
+            }
+            writeln!(svg, "</g>").ok();
+        }
+    }
+
+    writeln!(svg, "</svg>").ok();
+    svg
+}
+
+pub fn write_svg(
+    path: &Path,
+    contours: &PolyLines,
+    planet: &PlanetData,
+    options: &SvgOptions,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, planet_to_svg(contours, planet, options))?;
+    Ok(())
+}
+
+fn write_line(svg: &mut String, a: Vec2, b: Vec2) {
+    writeln!(
+        svg,
+        r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#,
+        a.x, a.y, b.x, b.y
+    )
+    .ok();
+}
@@ -0,0 +1,184 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use image::{ImageBuffer, Rgba};
+use planet::{
+    planet_data::PlanetData,
+    tile_map::Tile,
+};
+use serde_json::{json, Value};
+
+use crate::bevy_planet::conversions::{BLACK, GREEN, GREY, ORANGE, WHITE};
+
+/// Tile size in pixels of the exported tileset.
+const TILE_SIZE: u32 = 8;
+
+/// Tileset entries in gid order, a `Space` cell is left empty (gid 0).
+const TILE_CLASSES: [(&str, [u8; 4]); 5] = [
+    ("Space", BLACK),
+    ("Wall", GREY),
+    ("Room", WHITE),
+    ("RoomCenter", GREEN),
+    ("Tunnel", ORANGE),
+];
+
+fn tile_gid(tile: &Tile) -> u32 {
+    match tile {
+        Tile::Space => 0,
+        Tile::Wall => 2,
+        Tile::Room(_) => 3,
+        Tile::RoomCenter(_) => 4,
+        Tile::Tunnel(_) => 5,
+        _ => 0,
+    }
+}
+
+/// Writes the tile map as a Tiled json map, plus the tileset png it points to
+/// next to it. Tile types go in a tile layer, rooms in an object layer.
+pub fn write_tiled(path: &Path, planet: &PlanetData) -> Result<()> {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| anyhow!("bad tiled export path {}", path.display()))?;
+    let tileset_name = format!("{}_tiles.png", stem);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    tileset_image().save(path.with_file_name(&tileset_name))?;
+    fs::write(
+        path,
+        serde_json::to_string_pretty(&tiled_map(planet, &tileset_name))?,
+    )?;
+
+    Ok(())
+}
+
+pub fn tiled_map(planet: &PlanetData, tileset_image: &str) -> Value {
+    // the tile map is indexed [x][y], y pointing down
+    let tile_map = &planet.tile_map;
+    let width = tile_map.len();
+    let height = tile_map.first().map_or(0, |column| column.len());
+
+    let mut data = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            data.push(tile_gid(&tile_map[x][y]));
+        }
+    }
+
+    let mut objects = Vec::new();
+    let mut next_object_id = 1;
+    if let Some(roooms) = &planet.roooms {
+        for room in &roooms.rooms {
+            let (min, max) = room.tiles.iter().fold(
+                ((u32::MAX, u32::MAX), (0, 0)),
+                |(min, max), tile| {
+                    let (x, y) = (tile.x as u32, tile.y as u32);
+                    ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+                },
+            );
+            if room.tiles.is_empty() {
+                continue;
+            }
+
+            let properties = json!([
+                { "name": "room_id", "type": "int", "value": room.id as i64 },
+                { "name": "tile_count", "type": "int", "value": room.tiles.len() },
+            ]);
+            objects.push(json!({
+                "id": next_object_id,
+                "name": format!("room {}", room.id),
+                "type": "Room",
+                "x": min.0 * TILE_SIZE,
+                "y": min.1 * TILE_SIZE,
+                "width": (max.0 - min.0 + 1) * TILE_SIZE,
+                "height": (max.1 - min.1 + 1) * TILE_SIZE,
+                "rotation": 0,
+                "visible": true,
+                "properties": properties,
+            }));
+            objects.push(json!({
+                "id": next_object_id + 1,
+                "name": format!("room {} center", room.id),
+                "type": "RoomCenter",
+                "point": true,
+                "x": (room.center.x as f32 + 0.5) * TILE_SIZE as f32,
+                "y": (room.center.y as f32 + 0.5) * TILE_SIZE as f32,
+                "width": 0,
+                "height": 0,
+                "rotation": 0,
+                "visible": true,
+                "properties": properties,
+            }));
+            next_object_id += 2;
+        }
+    }
+
+    let tiles: Vec<Value> = TILE_CLASSES
+        .iter()
+        .enumerate()
+        .map(|(id, (class, _))| json!({ "id": id, "type": class }))
+        .collect();
+
+    json!({
+        "type": "map",
+        "version": "1.10",
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "infinite": false,
+        "width": width,
+        "height": height,
+        "tilewidth": TILE_SIZE,
+        "tileheight": TILE_SIZE,
+        "nextlayerid": 3,
+        "nextobjectid": next_object_id,
+        "layers": [
+            {
+                "id": 1,
+                "name": "tiles",
+                "type": "tilelayer",
+                "x": 0,
+                "y": 0,
+                "width": width,
+                "height": height,
+                "opacity": 1,
+                "visible": true,
+                "data": data,
+            },
+            {
+                "id": 2,
+                "name": "rooms",
+                "type": "objectgroup",
+                "draworder": "topdown",
+                "x": 0,
+                "y": 0,
+                "opacity": 1,
+                "visible": true,
+                "objects": objects,
+            },
+        ],
+        "tilesets": [
+            {
+                "firstgid": 1,
+                "name": "planet",
+                "image": tileset_image,
+                "imagewidth": TILE_SIZE * TILE_CLASSES.len() as u32,
+                "imageheight": TILE_SIZE,
+                "tilewidth": TILE_SIZE,
+                "tileheight": TILE_SIZE,
+                "tilecount": TILE_CLASSES.len(),
+                "columns": TILE_CLASSES.len(),
+                "margin": 0,
+                "spacing": 0,
+                "tiles": tiles,
+            },
+        ],
+    })
+}
+
+fn tileset_image() -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    ImageBuffer::from_fn(TILE_SIZE * TILE_CLASSES.len() as u32, TILE_SIZE, |x, _| {
+        Rgba(TILE_CLASSES[(x / TILE_SIZE) as usize].1)
+    })
+}
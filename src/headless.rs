@@ -0,0 +1,130 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use planet::planet_data::march_squares_rgba;
+use strum::IntoEnumIterator;
+
+use crate::{
+    bevy_planet::{
+        conversions::{bevy_image_to_imagebuffer, bitmap_display_to_bevy_image},
+        lib::build_planet,
+    },
+    export::{
+        svg::{write_svg, SvgOptions},
+        tiled::write_tiled,
+    },
+    sweep::{run_sweep, SweepSpec},
+    ui_state::{BitmapDisplay, UiState},
+};
+
+const USAGE: &str = "usage:
+    rustroneer export-svg <preset.yaml> <out.svg> [--world] [--rooms] [--mst] [--triangulation]
+    rustroneer export-tiled <preset.yaml> <out.json>
+    rustroneer dump-layers <preset.yaml> <out dir>
+    rustroneer sweep <preset.yaml> <sweep.yaml> <out dir>";
+
+/// Runs a command line subcommand without opening a window. Returns `None` if
+/// `args` (without the program name) don't name one, so the game should start.
+pub fn run(args: &[String]) -> Option<Result<()>> {
+    let (command, args) = args.split_first()?;
+    match command.as_str() {
+        "export-svg" => Some(export_svg(args)),
+        "export-tiled" => Some(export_tiled(args)),
+        "dump-layers" => Some(dump_layers(args)),
+        "sweep" => Some(sweep(args)),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+/// Splits `args` into positional arguments and `--flags`.
+fn parse_args(args: &[String]) -> (Vec<&str>, Vec<&str>) {
+    args.iter()
+        .map(String::as_str)
+        .partition(|arg| !arg.starts_with("--"))
+}
+
+fn load_preset(path: &str) -> Result<UiState> {
+    UiState::load_from(&PathBuf::from(path))
+        .map_err(|err| anyhow!("error loading preset {}: {}", path, err))
+}
+
+fn export_svg(args: &[String]) -> Result<()> {
+    let (positional, flags) = parse_args(args);
+    let [preset, out] = positional[..] else {
+        return Err(anyhow!("{}", USAGE));
+    };
+
+    let state = load_preset(preset)?;
+    let planet = build_planet(&state).map_err(|err| anyhow!(err))?;
+    let contours = march_squares_rgba(&planet.image).map_err(|err| anyhow!("{}", err))?;
+
+    let options = SvgOptions {
+        world_scale: flags.contains(&"--world").then_some(state.scale),
+        room_centers: flags.contains(&"--rooms"),
+        mst: flags.contains(&"--mst"),
+        triangulation: flags.contains(&"--triangulation"),
+    };
+    write_svg(&PathBuf::from(out), &contours, &planet, &options)?;
+    println!("wrote {}", out);
+
+    Ok(())
+}
+
+fn export_tiled(args: &[String]) -> Result<()> {
+    let (positional, _) = parse_args(args);
+    let [preset, out] = positional[..] else {
+        return Err(anyhow!("{}", USAGE));
+    };
+
+    let state = load_preset(preset)?;
+    let planet = build_planet(&state).map_err(|err| anyhow!(err))?;
+    write_tiled(&PathBuf::from(out), &planet)?;
+    println!("wrote {}", out);
+
+    Ok(())
+}
+
+/// Writes a png per `BitmapDisplay` variant, using the same converters as the texture plane.
+fn dump_layers(args: &[String]) -> Result<()> {
+    let (positional, _) = parse_args(args);
+    let [preset, out] = positional[..] else {
+        return Err(anyhow!("{}", USAGE));
+    };
+
+    let state = load_preset(preset)?;
+    let planet = build_planet(&state).map_err(|err| anyhow!(err))?;
+
+    let out = PathBuf::from(out);
+    fs::create_dir_all(&out)?;
+    for display in BitmapDisplay::iter() {
+        let Some(image) = bitmap_display_to_bevy_image(&planet, &display, &state.map_style)
+            .as_ref()
+            .and_then(bevy_image_to_imagebuffer)
+        else {
+            println!("skipped {:?}, the planet has no such layer", display);
+            continue;
+        };
+
+        let path = out.join(format!("{:?}.png", display));
+        image.save(&path)?;
+        println!("wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn sweep(args: &[String]) -> Result<()> {
+    let (positional, _) = parse_args(args);
+    let [preset, spec, out] = positional[..] else {
+        return Err(anyhow!("{}", USAGE));
+    };
+
+    let state = load_preset(preset)?;
+    let spec: SweepSpec = serde_yaml::from_str(&fs::read_to_string(spec)?)
+        .map_err(|err| anyhow!("error reading sweep {}: {}", spec, err))?;
+    run_sweep(&state, &spec, &PathBuf::from(out))
+}
@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use planet::planet_data::PlanetData;
+
+use crate::{
+    bevy_planet::{
+        lib::{is_solid, BevyPlanet},
+        planet_space::PlanetSpace,
+    },
+    ui::CursorWorldPosition,
+    ui_state::UiState,
+};
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, hover_inspector_system);
+    }
+}
+
+/// What the planet holds under one image pixel.
+struct HoverInfo {
+    pixel: UVec2,
+    tile: Option<(UVec2, String)>,
+    altitude: Option<f32>,
+    depth: Option<f32>,
+    mask: Option<f32>,
+    solid: bool,
+}
+
+/// Looks `map` up at `pixel`, maps may have another resolution than the image.
+fn sample_map<T: Copy>(map: &[Vec<T>], pixel: UVec2, dimension: u32) -> Option<T> {
+    let width = map.len() as u32;
+    let height = map.first()?.len() as u32;
+    let x = pixel.x * width / dimension;
+    let y = pixel.y * height / dimension;
+    map.get(x as usize)
+        .and_then(|column| column.get(y as usize))
+        .copied()
+}
+
+fn hover_info(planet: &PlanetData, space: &PlanetSpace, pixel: UVec2) -> HoverInfo {
+    let dimension = space.dimension();
+    let map = &planet.planet_map;
+    let tile = space.pixel_to_tile(pixel);
+
+    HoverInfo {
+        pixel,
+        tile: planet
+            .tile_map
+            .get(tile.x as usize)
+            .and_then(|column| column.get(tile.y as usize))
+            .map(|t| (tile, format!("{:?}", t))),
+        altitude: sample_map(&map.altitude, pixel, dimension),
+        depth: sample_map(&map.depth, pixel, dimension),
+        mask: map
+            .mask
+            .as_ref()
+            .and_then(|mask| sample_map(mask, pixel, dimension)),
+        solid: is_solid(planet.image.get_pixel(pixel.x, pixel.y)),
+    }
+}
+
+fn hover_inspector_system(
+    mut contexts: EguiContexts,
+    state: Res<UiState>,
+    cursor: Res<CursorWorldPosition>,
+    planet_query: Query<(&Name, &BevyPlanet, &GlobalTransform)>,
+) {
+    if !state.show_inspector {
+        return;
+    }
+    let Some(pos) = cursor.pos else {
+        return;
+    };
+
+    let hovered = planet_query.iter().find_map(|(name, bevy_planet, transform)| {
+        let planet = bevy_planet.planet_data.as_ref()?;
+        let space = PlanetSpace::from_planet(transform, planet);
+        let pixel = space.pixel_in_image(space.world_to_pixel(pos))?;
+        Some((name, hover_info(planet, &space, pixel)))
+    });
+    let Some((name, info)) = hovered else {
+        return;
+    };
+
+    let value = |v: Option<f32>| v.map_or("-".to_string(), |v| format!("{:.3}", v));
+
+    egui::Window::new("Inspector")
+        .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-10., 10.))
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("hover_inspector").show(ui, |ui| {
+                ui.label("planet");
+                ui.label(name.as_str());
+                ui.end_row();
+                ui.label("pixel");
+                ui.label(format!("{}, {}", info.pixel.x, info.pixel.y));
+                ui.end_row();
+                if let Some((tile_pos, tile)) = &info.tile {
+                    ui.label("tile");
+                    ui.label(format!("{}, {}", tile_pos.x, tile_pos.y));
+                    ui.end_row();
+                    ui.label("contents");
+                    ui.label(tile);
+                    ui.end_row();
+                }
+                ui.label("altitude");
+                ui.label(value(info.altitude));
+                ui.end_row();
+                ui.label("depth");
+                ui.label(value(info.depth));
+                ui.end_row();
+                ui.label("mask");
+                ui.label(value(info.mask));
+                ui.end_row();
+                ui.label("solid");
+                ui.label(info.solid.to_string());
+                ui.end_row();
+            });
+        });
+}
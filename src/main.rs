@@ -3,17 +3,27 @@ use bevy::{
     prelude::*,
     window::WindowMode,
 };
+use asset_stats::AssetStatsPlugin;
 use bevy_rapier2d::prelude::*;
 use bevy_tweening::*;
 use camera::MyCameraPlugin;
+use export::ExportPlugin;
+use inspector::InspectorPlugin;
+use pathfinding::PathfindingPlugin;
 use physics::PhysicsPlugin;
 use player::MyPlayerPlugin;
 use ui::PlanetUiPlugin;
 use planet_gizmos::PlanetGizmosPlugin;
+use room_report::RoomReportPlugin;
 
+mod asset_stats;
 mod bevy_planet;
 mod camera;
+mod export;
+mod headless;
+mod inspector;
 mod line;
+mod pathfinding;
 mod physics;
 mod player;
 mod traits;
@@ -21,12 +31,25 @@ mod types;
 mod ui;
 mod ui_state;
 mod planet_gizmos;
+mod planet_stats;
+mod room_report;
+mod sweep;
 mod vector_shapes;
 
 #[derive(Component)]
 pub struct PlayerTag;
 
 fn main() {
+    // subcommands run headless, without the fullscreen window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = headless::run(&args) {
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     match dotenv::dotenv() {
         Ok(_) => {
             println!("loaded env")
@@ -65,6 +88,11 @@ fn main() {
         ))
         .add_plugins(TweeningPlugin)
         .add_plugins(PlanetGizmosPlugin)
+        .add_plugins(ExportPlugin)
+        .add_plugins(AssetStatsPlugin)
+        .add_plugins(InspectorPlugin)
+        .add_plugins(RoomReportPlugin)
+        .add_plugins(PathfindingPlugin)
         .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
         .run();
 }
@@ -0,0 +1,433 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use bevy::{prelude::*, utils::tracing};
+use planet::{
+    planet_data::PlanetData,
+    tile_map::{Tile, TileMap},
+};
+
+use crate::{
+    bevy_planet::{
+        lib::{is_solid, BevyPlanet, EditedTiles},
+        planet_space::PlanetSpace,
+    },
+    player::MyPlayerTag,
+    ui::{ContoursChangedEvent, MouseClickWorldEvent},
+    ui_state::UiState,
+};
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerPath>()
+            .add_systems(Update, (path_target_system, player_path_system).chain());
+    }
+}
+
+// step costs, diagonals are ~sqrt(2) times a straight step
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+
+// tiles a search may expand before it gives up, a 256x256 tile map's worth
+const SEARCH_BUDGET: usize = 1 << 16;
+
+/// Why no path was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// the start or goal isn't on the tile map
+    OutsideMap,
+    /// the goal is rock or walled off from the start
+    Unreachable,
+    /// the search expanded `SEARCH_BUDGET` tiles without reaching the goal,
+    /// which may still be reachable
+    BudgetExceeded,
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PathError::OutsideMap => write!(f, "start or goal outside the tile map"),
+            PathError::Unreachable => write!(f, "goal can't be reached"),
+            PathError::BudgetExceeded => {
+                write!(f, "gave up after searching {} tiles", SEARCH_BUDGET)
+            }
+        }
+    }
+}
+
+/// Rock blocks, everything carved out of it can be walked through.
+pub fn is_passable(tile: &Tile) -> bool {
+    matches!(
+        tile,
+        Tile::Space | Tile::Room(_) | Tile::RoomCenter(_) | Tile::Tunnel(_)
+    )
+}
+
+/// Which tiles can be walked through, indexed like the tile map.
+pub struct Passability {
+    size: UVec2,
+    open: Vec<bool>,
+}
+
+impl Passability {
+    pub fn from_tile_map(tile_map: &TileMap) -> Self {
+        let size = UVec2::new(
+            tile_map.len() as u32,
+            tile_map.first().map_or(0, |column| column.len()) as u32,
+        );
+        let mut open = vec![false; (size.x * size.y) as usize];
+        for (x, column) in tile_map.iter().enumerate() {
+            for (y, tile) in column.iter().enumerate().take(size.y as usize) {
+                open[x * size.y as usize + y] = is_passable(tile);
+            }
+        }
+        Self { size, open }
+    }
+
+    /// The tile map, except on tiles edited by hand where the brush has made
+    /// it stale. Those are open if the image is open at the tile center.
+    pub fn for_planet(planet: &PlanetData, space: &PlanetSpace, edited: &EditedTiles) -> Self {
+        let mut passability = Self::from_tile_map(&planet.tile_map);
+        for tile in &edited.tiles {
+            let Some(index) = passability.index(tile.x as i32, tile.y as i32) else {
+                continue;
+            };
+            let center = space.local_to_pixel(space.tile_to_local(*tile));
+            if let Some(pixel) = space.pixel_in_image(center) {
+                passability.open[index] = !is_solid(planet.image.get_pixel(pixel.x, pixel.y));
+            }
+        }
+        passability
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let inside = x >= 0 && y >= 0 && (x as u32) < self.size.x && (y as u32) < self.size.y;
+        inside.then(|| x as usize * self.size.y as usize + y as usize)
+    }
+
+    pub fn is_open(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some_and(|index| self.open[index])
+    }
+}
+
+/// Octile distance, exact on an empty 8-connected grid.
+fn heuristic(a: UVec2, b: UVec2) -> u32 {
+    let dx = a.x.abs_diff(b.x);
+    let dy = a.y.abs_diff(b.y);
+    STRAIGHT * dx.max(dy) + (DIAGONAL - STRAIGHT) * dx.min(dy)
+}
+
+/// `find_path` over a tile map as generated.
+pub fn find_tile_path(
+    tile_map: &TileMap,
+    start: UVec2,
+    goal: UVec2,
+) -> Result<Vec<UVec2>, PathError> {
+    find_path(&Passability::from_tile_map(tile_map), start, goal)
+}
+
+/// A* over the tiles, 8-connected without cutting wall corners. The path
+/// runs from `start` to `goal` inclusive. `start` may be blocked, so paths can
+/// leave a wall the entity got pushed into; `goal` may not. Gives up after
+/// `SEARCH_BUDGET` tiles, an unreachable goal otherwise costs a flood of every
+/// tile the start connects to.
+pub fn find_path(
+    passability: &Passability,
+    start: UVec2,
+    goal: UVec2,
+) -> Result<Vec<UVec2>, PathError> {
+    let index = |x: i32, y: i32| passability.index(x, y);
+    let goal_index = index(goal.x as i32, goal.y as i32).ok_or(PathError::OutsideMap)?;
+    let start_index = index(start.x as i32, start.y as i32).ok_or(PathError::OutsideMap)?;
+    if !passability.open[goal_index] {
+        return Err(PathError::Unreachable);
+    }
+
+    let tiles = passability.open.len();
+    let mut cost = vec![u32::MAX; tiles];
+    let mut came_from = vec![usize::MAX; tiles];
+    let mut open = BinaryHeap::new();
+
+    cost[start_index] = 0;
+    open.push(Reverse((heuristic(start, goal), 0, start.x, start.y)));
+
+    let height = passability.size.y as usize;
+    let mut expanded = 0;
+    while let Some(Reverse((_, g, x, y))) = open.pop() {
+        let current = x as usize * height + y as usize;
+        if current == goal_index {
+            let mut path = vec![goal];
+            let mut tile = goal_index;
+            while came_from[tile] != usize::MAX {
+                tile = came_from[tile];
+                path.push(UVec2::new((tile / height) as u32, (tile % height) as u32));
+            }
+            path.reverse();
+            return Ok(path);
+        }
+        // a cheaper way here was found after this entry was queued
+        if g > cost[current] {
+            continue;
+        }
+        expanded += 1;
+        if expanded > SEARCH_BUDGET {
+            return Err(PathError::BudgetExceeded);
+        }
+
+        for dx in -1i32..=1 {
+            for dy in -1i32..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                let Some(next) = index(nx, ny).filter(|next| passability.open[*next]) else {
+                    continue;
+                };
+                let diagonal = dx != 0 && dy != 0;
+                if diagonal
+                    && !(passability.is_open(x as i32 + dx, y as i32)
+                        && passability.is_open(x as i32, y as i32 + dy))
+                {
+                    continue;
+                }
+
+                let next_cost = g + if diagonal { DIAGONAL } else { STRAIGHT };
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    came_from[next] = current;
+                    let tile = UVec2::new(nx as u32, ny as u32);
+                    open.push(Reverse((
+                        next_cost + heuristic(tile, goal),
+                        next_cost,
+                        tile.x,
+                        tile.y,
+                    )));
+                }
+            }
+        }
+    }
+
+    Err(PathError::Unreachable)
+}
+
+/// A world space path across the planet between two world points, through
+/// tile centers.
+pub fn find_world_path(
+    planet: &PlanetData,
+    space: &PlanetSpace,
+    edited: &EditedTiles,
+    from: Vec3,
+    to: Vec3,
+) -> Result<Vec<Vec3>, PathError> {
+    let start = space.world_to_tile(from).ok_or(PathError::OutsideMap)?;
+    let goal = space.world_to_tile(to).ok_or(PathError::OutsideMap)?;
+    let path = find_path(&Passability::for_planet(planet, space, edited), start, goal)?;
+    Ok(path.into_iter().map(|tile| space.tile_to_world(tile)).collect())
+}
+
+/// A world space path between the centers of two rooms, by room id. Rooms the
+/// planet doesn't have are `OutsideMap`.
+pub fn find_room_path(
+    planet: &PlanetData,
+    space: &PlanetSpace,
+    edited: &EditedTiles,
+    from_room: u64,
+    to_room: u64,
+) -> Result<Vec<Vec3>, PathError> {
+    let rooms = &planet.roooms.as_ref().ok_or(PathError::OutsideMap)?.rooms;
+    let center = |id: u64| {
+        rooms
+            .iter()
+            .find(|room| room.id as u64 == id)
+            .map(|room| UVec2::new(room.center.x as u32, room.center.y as u32))
+            .ok_or(PathError::OutsideMap)
+    };
+    let passability = Passability::for_planet(planet, space, edited);
+    let path = find_path(&passability, center(from_room)?, center(to_room)?)?;
+    Ok(path.into_iter().map(|tile| space.tile_to_world(tile)).collect())
+}
+
+/// The route from the player to the last middle clicked point.
+#[derive(Resource, Default)]
+pub struct PlayerPath {
+    pub target: Option<Vec3>,
+    pub path: Vec<Vec3>,
+    /// the player tile and target the path was found for
+    searched: Option<(Vec3, UVec2)>,
+}
+
+fn path_target_system(
+    mut events: EventReader<MouseClickWorldEvent>,
+    mut player_path: ResMut<PlayerPath>,
+) {
+    for event in events.read() {
+        if event.button == MouseButton::Middle {
+            player_path.target = Some(event.pos);
+        }
+    }
+}
+
+/// Searches again when the target or the player's tile changes, or the
+/// terrain under the path does.
+fn player_path_system(
+    state: Res<UiState>,
+    mut player_path: ResMut<PlayerPath>,
+    mut contours_changed: EventReader<ContoursChangedEvent>,
+    player_query: Query<&GlobalTransform, With<MyPlayerTag>>,
+    planet_query: Query<(&BevyPlanet, &GlobalTransform, &EditedTiles)>,
+    changed_planets: Query<(), Or<(Changed<BevyPlanet>, Changed<EditedTiles>)>>,
+) {
+    // regenerated, edited, undone or re-simplified since the last search
+    let terrain_changed = contours_changed.read().count() > 0 || !changed_planets.is_empty();
+    if terrain_changed {
+        player_path.searched = None;
+    }
+
+    if !state.gizmo_options.draw_path {
+        return;
+    }
+    let (Some(target), Ok(player)) = (player_path.target, player_query.get_single()) else {
+        return;
+    };
+    let from = player.translation();
+
+    let found = planet_query.iter().find_map(|(bevy_planet, transform, edited)| {
+        let planet = bevy_planet.planet_data.as_ref()?;
+        let space = PlanetSpace::from_planet(transform, planet);
+        let player_tile = space.world_to_tile(from)?;
+        space.world_to_tile(target)?;
+        Some((planet, space, edited, player_tile))
+    });
+    let Some((planet, space, edited, player_tile)) = found else {
+        player_path.path.clear();
+        player_path.searched = None;
+        return;
+    };
+
+    if player_path.searched == Some((target, player_tile)) {
+        return;
+    }
+    player_path.searched = Some((target, player_tile));
+    player_path.path = match find_world_path(planet, &space, edited, from, target) {
+        Ok(path) => path,
+        Err(err) => {
+            // the target may well be reachable, say why the path is missing
+            if err == PathError::BudgetExceeded {
+                tracing::warn!("no path to the target: {}", err);
+            }
+            Vec::new()
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tile map from rows of `.` for space and `#` for rock, row 0 on top.
+    fn tile_map(rows: &[&str]) -> TileMap {
+        let rows: Vec<Vec<char>> = rows.iter().map(|row| row.chars().collect()).collect();
+        (0..rows[0].len())
+            .map(|x| {
+                rows.iter()
+                    .map(|row| match row[x] {
+                        '#' => Tile::Wall,
+                        _ => Tile::Space,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_no_corner_cutting(map: &TileMap, path: &[UVec2]) {
+        let passability = Passability::from_tile_map(map);
+        for step in path.windows(2) {
+            let (a, b) = (step[0].as_ivec2(), step[1].as_ivec2());
+            let d = b - a;
+            assert!(d.x.abs() <= 1 && d.y.abs() <= 1, "{:?} jumps", step);
+            if d.x != 0 && d.y != 0 {
+                assert!(passability.is_open(a.x + d.x, a.y), "{:?} cuts a corner", step);
+                assert!(passability.is_open(a.x, a.y + d.y), "{:?} cuts a corner", step);
+            }
+        }
+    }
+
+    #[test]
+    fn straight_path() {
+        let map = tile_map(&["....."]);
+        let path = find_tile_path(&map, UVec2::new(0, 0), UVec2::new(4, 0)).unwrap();
+        assert_eq!(path, (0..5).map(|x| UVec2::new(x, 0)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn diagonal_path_on_open_ground() {
+        let map = tile_map(&["...", "...", "..."]);
+        let path = find_tile_path(&map, UVec2::new(0, 0), UVec2::new(2, 2)).unwrap();
+        assert_eq!(path, vec![UVec2::new(0, 0), UVec2::new(1, 1), UVec2::new(2, 2)]);
+    }
+
+    #[test]
+    fn no_corner_cutting() {
+        let map = tile_map(&[".#.", "...", "..."]);
+        let path = find_tile_path(&map, UVec2::new(0, 0), UVec2::new(2, 0)).unwrap();
+        assert_no_corner_cutting(&map, &path);
+        // round the rock on straight steps, both diagonals would clip it
+        assert_eq!(path.len(), 5);
+
+        // two rock tiles touching at a corner can't be slipped between
+        let map = tile_map(&[".#", "#."]);
+        assert_eq!(
+            find_tile_path(&map, UVec2::new(0, 0), UVec2::new(1, 1)),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn blocked_goal() {
+        let map = tile_map(&["..#"]);
+        assert_eq!(
+            find_tile_path(&map, UVec2::new(0, 0), UVec2::new(2, 0)),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        let map = tile_map(&[".#.", ".#.", ".#."]);
+        assert_eq!(
+            find_tile_path(&map, UVec2::new(0, 0), UVec2::new(2, 2)),
+            Err(PathError::Unreachable)
+        );
+    }
+
+    #[test]
+    fn goal_outside_the_map() {
+        let map = tile_map(&["..."]);
+        assert_eq!(
+            find_tile_path(&map, UVec2::new(0, 0), UVec2::new(3, 0)),
+            Err(PathError::OutsideMap)
+        );
+    }
+
+    #[test]
+    fn giving_up_is_not_unreachable() {
+        // an open map bigger than the budget, with the goal walled into a corner
+        let mut map: TileMap = (0..300).map(|_| (0..300).map(|_| Tile::Space).collect()).collect();
+        for (x, y) in [(298, 298), (298, 299), (299, 298)] {
+            map[x][y] = Tile::Wall;
+        }
+        assert_eq!(
+            find_tile_path(&map, UVec2::new(0, 0), UVec2::new(299, 299)),
+            Err(PathError::BudgetExceeded)
+        );
+    }
+
+    #[test]
+    fn start_in_rock_can_walk_out() {
+        let map = tile_map(&["#.."]);
+        let path = find_tile_path(&map, UVec2::new(0, 0), UVec2::new(2, 0)).unwrap();
+        assert_eq!(path.first(), Some(&UVec2::new(0, 0)));
+        assert_eq!(path.last(), Some(&UVec2::new(2, 0)));
+    }
+}
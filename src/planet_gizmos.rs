@@ -1,5 +1,11 @@
 use crate::{
-    bevy_planet::lib::BevyPlanet, ui_state::UiState,
+    bevy_planet::{
+        chunks::{chunk_rect, TerrainChunk},
+        lib::BevyPlanet,
+        planet_space::PlanetSpace,
+    },
+    pathfinding::PlayerPath,
+    ui_state::UiState,
 };
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -8,49 +14,40 @@ pub struct PlanetGizmosPlugin;
 impl Plugin for PlanetGizmosPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Update, draw_gizmos);
+        app.add_systems(Update, draw_collider_chunks);
+        app.add_systems(Update, draw_player_path);
     }
 }
 
 fn draw_gizmos(
     mut gizmos: Gizmos,
     ui_state: Res<UiState>,
-    planet_query: Query<&BevyPlanet, With<Name>>,
+    planet_query: Query<(&BevyPlanet, &GlobalTransform), With<Name>>,
 ) {
     if ui_state.gizmo_options.draw_gizmos == false {
         return;
     }
 
-    for bevy_planet in planet_query.iter() {
+    for (bevy_planet, planet_transform) in planet_query.iter() {
         if let Some(planet_data) = &bevy_planet.planet_data
         {
-            let dimension = planet_data.get_dimension();
-            let dimension = dimension as u32;
-            let scale = ui_state.scale;
+            let space = PlanetSpace::from_planet(planet_transform, planet_data);
+            let scale = planet_transform.compute_transform().scale.x;
+            // room data is in tiles, drawn over the texture plane of each planet
+            let to_world = |tile: UVec2| {
+                space.texture_to_world(space.tile_to_local(tile)).truncate()
+            };
 
             if let Some(roooms) = &planet_data.roooms {
-                let o = Vec2::new(-1.7, 0.);
-
                 if ui_state.gizmo_options.draw_mst {
                     // draw mst
                     if let Some(mst) = &roooms.mst {
                         for i in mst {
-                            let a = ((roooms.rooms[i.0]
-                                .center
-                                .into_world_normalized_vec2(
-                                    &dimension,
-                                ))
-                                + o)
-                                * scale;
-                            let b = ((roooms.rooms[i.1]
-                                .center
-                                .into_world_normalized_vec2(
-                                    &dimension,
-                                ))
-                                + o)
-                                * scale;
+                            let a = &roooms.rooms[i.0].center;
+                            let b = &roooms.rooms[i.1].center;
                             gizmos.line_2d(
-                                a,
-                                b,
+                                to_world(UVec2::new(a.x as u32, a.y as u32)),
+                                to_world(UVec2::new(b.x as u32, b.y as u32)),
                                 Color::PINK,
                             );
                         }
@@ -59,13 +56,7 @@ fn draw_gizmos(
                 if ui_state.gizmo_options.draw_centers {
                     // draw room centers
                     roooms.rooms.iter().for_each(|r| {
-                        let c = (r
-                            .center
-                            .into_world_normalized_vec2(
-                                &dimension,
-                            )
-                            + o)
-                            * scale;
+                        let c = to_world(UVec2::new(r.center.x as u32, r.center.y as u32));
                         gizmos.circle_2d(
                             c,
                             scale * 0.01,
@@ -81,28 +72,11 @@ fn draw_gizmos(
                         roooms.get_triangulation_coords()
                     {
                         tr.iter().for_each(|t| {
-                            let start = (t
-                                .0
-                                .into_world_normalized_vec2(
-                                    &dimension,
-                                ))
-                                + o * scale;
-                            let end = (t
-                                .1
-                                .into_world_normalized_vec2(
-                                    &dimension,
-                                ))
-                                + o * scale;
-
                             gizmos.line_2d(
-                            (t.0.into_world_normalized_vec2(
-                                &dimension,
-                            ) + o) * scale,
-                            (t.1.into_world_normalized_vec2(
-                                &dimension,
-                            ) + o) * scale,
-                            Color::RED,
-                        )
+                                to_world(UVec2::new(t.0.x as u32, t.0.y as u32)),
+                                to_world(UVec2::new(t.1.x as u32, t.1.y as u32)),
+                                Color::RED,
+                            )
                         })
                     }
                 }
@@ -111,6 +85,51 @@ fn draw_gizmos(
     }
 }
 
+fn draw_collider_chunks(
+    mut gizmos: Gizmos,
+    ui_state: Res<UiState>,
+    chunk_query: Query<(&TerrainChunk, &GlobalTransform)>,
+) {
+    if !ui_state.show_debug {
+        return;
+    }
+
+    for (chunk, transform) in chunk_query.iter() {
+        let rect = chunk_rect(chunk.coord);
+        let corners = [
+            rect.min,
+            Vec2::new(rect.max.x, rect.min.y),
+            rect.max,
+            Vec2::new(rect.min.x, rect.max.y),
+            rect.min,
+        ];
+        gizmos.linestrip_2d(
+            corners.map(|c| {
+                transform.transform_point(c.extend(0.)).truncate()
+            }),
+            Color::ORANGE,
+        );
+    }
+}
+
+fn draw_player_path(
+    mut gizmos: Gizmos,
+    ui_state: Res<UiState>,
+    player_path: Res<PlayerPath>,
+) {
+    if !ui_state.gizmo_options.draw_gizmos || !ui_state.gizmo_options.draw_path {
+        return;
+    }
+
+    gizmos.linestrip_2d(
+        player_path.path.iter().map(|p| p.truncate()),
+        Color::CYAN,
+    );
+    if let Some(target) = player_path.target {
+        gizmos.circle_2d(target.truncate(), 2., Color::CYAN);
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GizmoOptions {
     pub draw_gizmos: bool,
@@ -118,6 +137,9 @@ pub struct GizmoOptions {
     pub draw_triangulation: bool,
     pub draw_mst: bool,
     pub offset: bool,
+    /// the tile path from the player to the middle clicked point
+    #[serde(default)]
+    pub draw_path: bool,
 }
 
 // fn doubler<T: Clone>(input: Vec<T>) -> Option<Vec<(T, T)>> {
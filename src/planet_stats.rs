@@ -0,0 +1,51 @@
+use planet::{planet_data::PlanetData, tile_map::Tile};
+
+use crate::bevy_planet::lib::is_solid;
+
+/// Summary numbers for comparing generated planets.
+#[derive(Debug, Clone, Default)]
+pub struct PlanetStats {
+    pub room_count: usize,
+    pub room_tiles: usize,
+    pub tunnel_tiles: usize,
+    pub wall_tiles: usize,
+    /// fraction of image pixels that are rock
+    pub solid_ratio: f32,
+}
+
+impl PlanetStats {
+    pub const CSV_HEADER: &'static str =
+        "room_count,room_tiles,tunnel_tiles,wall_tiles,solid_ratio";
+
+    pub fn from_planet(planet: &PlanetData) -> Self {
+        let mut stats = PlanetStats {
+            room_count: planet
+                .roooms
+                .as_ref()
+                .map_or(0, |roooms| roooms.rooms.len()),
+            ..Default::default()
+        };
+
+        for tile in planet.tile_map.iter().flatten() {
+            match tile {
+                Tile::Room(_) | Tile::RoomCenter(_) => stats.room_tiles += 1,
+                Tile::Tunnel(_) => stats.tunnel_tiles += 1,
+                Tile::Wall => stats.wall_tiles += 1,
+                _ => {}
+            }
+        }
+
+        let pixels = planet.image.pixels().len().max(1);
+        let solid = planet.image.pixels().filter(|p| is_solid(p)).count();
+        stats.solid_ratio = solid as f32 / pixels as f32;
+
+        stats
+    }
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{:.4}",
+            self.room_count, self.room_tiles, self.tunnel_tiles, self.wall_tiles, self.solid_ratio
+        )
+    }
+}
@@ -6,6 +6,7 @@ use bevy_rapier2d::{
 use rand::Rng;
 
 use crate::{
+    bevy_planet::lib::{PlanetRootTag, PlanetSettings},
     line::{LineList, LineMaterial, LineStrip}, traits::IntoVec2, ui::{GeneralUpdateEvent, RegeneratePlanetEvent}, ui_state::{self, CameraMode}, vector_shapes::{RCircle, RRectangle}
 };
 
@@ -14,6 +15,7 @@ pub struct MyPlayerPlugin;
 impl Plugin for MyPlayerPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<RocketStatusResource>();
+        app.init_resource::<GravityCenter>();
         app.add_systems(Startup, spawn_player);
         app.add_systems(Update, (user_input, apply_angle));
         app.add_systems(
@@ -84,6 +86,31 @@ impl Default for RocketStatus {
     }
 }
 
+/// Center of the planet currently pulling on the player.
+#[derive(Resource, Default)]
+pub struct GravityCenter {
+    pub center: Vec2,
+}
+
+/// Picks the planet with the strongest pull on `position`. Pull scales with
+/// the planet's area over the squared distance to its center.
+fn strongest_planet_center<'a>(
+    position: Vec2,
+    planets: impl Iterator<Item = (&'a GlobalTransform, &'a PlanetSettings)>,
+) -> Option<Vec2> {
+    planets
+        .map(|(transform, settings)| {
+            let center = transform.translation().truncate();
+            let radius = settings.ui_state.radius
+                * transform.compute_transform().scale.x;
+            let distance_squared =
+                position.distance_squared(center).max(f32::EPSILON);
+            (center, radius * radius / distance_squared)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(center, _)| center)
+}
+
 fn spawn_player(
     mut cmd: Commands,
     ui_state: Res<ui_state::UiState>,
@@ -113,7 +140,13 @@ fn spawn_player(
         });
 }
 
-fn get_spawn_point(scale: f32, radius: f32) -> Vec3 {
+/// The spawn point in a planet's own local space, just above the top of its
+/// surface. `get_spawn_point` is this point on the planet at the world origin.
+pub fn local_spawn_point(radius: f32) -> Vec2 {
+    Vec2::new(0.0, radius * 1.1)
+}
+
+pub fn get_spawn_point(scale: f32, radius: f32) -> Vec3 {
     let mut y_pos: f32;
 
     // let dimension = p.get_dimension();
@@ -296,7 +329,7 @@ fn spawn_rocket_ystem(
 fn update_rocket_mesh_system(
     mut meshes: ResMut<Assets<Mesh>>,
     mut rocket_query: Query<
-        (Entity, &mut Handle<Mesh>),
+        (Entity, &Handle<Mesh>),
         With<RocketTag>,
     >,
     mut rocket_transform_query: Query<
@@ -310,7 +343,7 @@ fn update_rocket_mesh_system(
     direction: Query<(&Direction), With<MyPlayerTag>>,
     rocket_status: Res<RocketStatusResource>,
 ) {
-    for (entity, mut mesh_handle) in rocket_query.iter_mut()
+    for (entity, mesh_handle) in rocket_query.iter_mut()
     {
         let mut rng = rand::thread_rng();
         let mut ro = || rng.gen_range(-0.5..0.5);
@@ -348,9 +381,11 @@ fn update_rocket_mesh_system(
                     }
                 }
 
-                let new_rocket_mesh = meshes
-                    .add(LineList { vertices: lines });
-                *mesh_handle = new_rocket_mesh;
+                // reuse the spawned mesh, a new one every frame churns gpu buffers
+                meshes.insert(
+                    mesh_handle.id(),
+                    LineList { vertices: lines }.into(),
+                );
             }
             RocketStatus::NotFiring => {
                 *visibility = Visibility::Hidden;
@@ -444,6 +479,11 @@ fn user_input(
     keyboard_input: Res<ButtonInput<KeyCode>>,
     ui_state: Res<ui_state::UiState>,
     mut rocket_status: ResMut<RocketStatusResource>,
+    planet_query: Query<
+        (&GlobalTransform, &PlanetSettings),
+        With<PlanetRootTag>,
+    >,
+    mut gravity_center: ResMut<GravityCenter>,
 ) {
     let move_force = ui_state.player_move_force;
     let jetpack_force = ui_state.player_jetpack_force;
@@ -451,14 +491,22 @@ fn user_input(
     for (t, mut ef) in query.iter_mut() {
         rocket_status.status = RocketStatus::NotFiring;
 
+        gravity_center.center = strongest_planet_center(
+            t.translation.into_vec2(),
+            planet_query.iter(),
+        )
+        .unwrap_or(Vec2::ZERO);
+        let relative_position =
+            t.translation.into_vec2() - gravity_center.center;
+
         ef.force = Vec2::new(0.0, 0.0);
 
         let grav_scale = 5.;
-        ef.force = -t.translation.into_vec2().normalize()
+        ef.force = -relative_position.normalize()
             * grav_scale;
 
         let direction =
-            t.translation.truncate().normalize(); // Normalize to get direction
+            relative_position.normalize(); // Normalize to get direction
         let perp_clockwise =
             Vec2::new(-direction.y, direction.x); // Rotate 90 degrees clockwise
         let perp_counter_clockwise =
@@ -513,6 +561,7 @@ fn reset_player_system(
 fn apply_angle(
     mut q: Query<&mut Transform, With<MyPlayerTag>>,
     ui_state: Res<ui_state::UiState>,
+    gravity_center: Res<GravityCenter>,
 ) {
     let mut transform = q.single_mut();
 
@@ -521,11 +570,11 @@ fn apply_angle(
             transform.translation.x,
             transform.translation.y,
         ),
-        &Vec2::new(0.0, 0.0),
+        &gravity_center.center,
     );
 
     let t = (distance_to_center / 100.).clamp(0., 1.);
-    let direction = transform.translation - Vec3::ZERO; // Vec3::ZERO is the origin
+    let direction = transform.translation - gravity_center.center.extend(0.); // the planet currently pulling on the player
     let angle = f32::atan2(direction.x, direction.y);
     let target_rotatin = Quat::from_rotation_z(-angle);
     let current_rotation = transform.rotation;
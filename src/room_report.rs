@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use planet::planet_data::PlanetData;
+
+use crate::{
+    bevy_planet::{
+        lib::{ActivePlanet, BevyPlanet, PlanetSettings},
+        planet_space::PlanetSpace,
+    },
+    export::ExportEvent,
+    player::local_spawn_point,
+    ui_state::UiState,
+};
+
+pub struct RoomReportPlugin;
+
+impl Plugin for RoomReportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, room_report_window_system);
+    }
+}
+
+/// One room of the report. Tile coordinates are (x, y) like the tile map.
+#[derive(Debug, Clone)]
+pub struct RoomRow {
+    pub id: u64,
+    pub tiles: usize,
+    pub bbox_min: UVec2,
+    pub bbox_max: UVec2,
+    pub mst_degree: usize,
+    /// mst hops from the spawn room, `None` if it can't be reached
+    pub spawn_mst_hops: Option<usize>,
+}
+
+/// Per-room numbers and summary stats of a planet's rooms and their mst.
+#[derive(Debug, Clone, Default)]
+pub struct RoomReport {
+    pub rows: Vec<RoomRow>,
+    /// index into `rows` of the room the player spawns closest to
+    pub spawn_room: Option<usize>,
+    pub mean_size: f32,
+    pub largest: Option<usize>,
+    /// connected parts of the mst, 1 when every room can be reached
+    pub components: usize,
+}
+
+impl RoomReport {
+    pub const CSV_HEADER: &'static str =
+        "id,tiles,bbox_min_x,bbox_min_y,bbox_max_x,bbox_max_y,mst_degree,spawn_mst_hops";
+
+    pub fn from_planet(planet: &PlanetData, spawn_room: Option<usize>) -> Self {
+        let Some(roooms) = &planet.roooms else {
+            return RoomReport::default();
+        };
+        let rooms = &roooms.rooms;
+
+        let mut neighbours = vec![Vec::new(); rooms.len()];
+        for &(a, b) in roooms.mst.iter().flatten() {
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+        }
+
+        let spawn_hops = spawn_room.map(|spawn| hops_from(&neighbours, spawn));
+
+        let rows: Vec<RoomRow> = rooms
+            .iter()
+            .enumerate()
+            .map(|(i, room)| {
+                let mut bbox_min = UVec2::MAX;
+                let mut bbox_max = UVec2::ZERO;
+                for tile in &room.tiles {
+                    let tile = UVec2::new(tile.x as u32, tile.y as u32);
+                    bbox_min = bbox_min.min(tile);
+                    bbox_max = bbox_max.max(tile);
+                }
+                RoomRow {
+                    id: room.id as u64,
+                    tiles: room.tiles.len(),
+                    bbox_min: bbox_min.min(bbox_max),
+                    bbox_max,
+                    mst_degree: neighbours[i].len(),
+                    spawn_mst_hops: spawn_hops.as_ref().and_then(|hops| hops[i]),
+                }
+            })
+            .collect();
+
+        let mut components = 0;
+        let mut seen = vec![false; rooms.len()];
+        for start in 0..rooms.len() {
+            if seen[start] {
+                continue;
+            }
+            components += 1;
+            for (i, hops) in hops_from(&neighbours, start).iter().enumerate() {
+                seen[i] |= hops.is_some();
+            }
+        }
+
+        let total: usize = rows.iter().map(|row| row.tiles).sum();
+        RoomReport {
+            mean_size: total as f32 / rows.len().max(1) as f32,
+            largest: (0..rows.len()).max_by_key(|i| rows[*i].tiles),
+            components,
+            spawn_room,
+            rows,
+        }
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", Self::CSV_HEADER);
+        for row in &self.rows {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                row.id,
+                row.tiles,
+                row.bbox_min.x,
+                row.bbox_min.y,
+                row.bbox_max.x,
+                row.bbox_max.y,
+                row.mst_degree,
+                row.spawn_mst_hops.map_or(String::new(), |hops| hops.to_string()),
+            ));
+        }
+        csv
+    }
+}
+
+/// Breadth first hop counts from `start`, `None` for rooms it can't reach.
+fn hops_from(neighbours: &[Vec<usize>], start: usize) -> Vec<Option<usize>> {
+    let mut hops = vec![None; neighbours.len()];
+    let mut queue = VecDeque::new();
+    if start < neighbours.len() {
+        hops[start] = Some(0);
+        queue.push_back(start);
+    }
+    while let Some(i) = queue.pop_front() {
+        let next = hops[i].map(|h| h + 1);
+        for &n in &neighbours[i] {
+            if hops[n].is_none() {
+                hops[n] = next;
+                queue.push_back(n);
+            }
+        }
+    }
+    hops
+}
+
+/// The room whose center is closest to the planet's spawn point. The point is
+/// taken in the planet's local space, so it means the same on every planet
+/// wherever it sits in the world.
+pub fn spawn_room(planet: &PlanetData, space: &PlanetSpace, settings: &UiState) -> Option<usize> {
+    let spawn = local_spawn_point(settings.radius);
+    let rooms = &planet.roooms.as_ref()?.rooms;
+    (0..rooms.len()).min_by(|a, b| {
+        let distance = |i: usize| {
+            let center = &rooms[i].center;
+            space
+                .tile_to_local(UVec2::new(center.x as u32, center.y as u32))
+                .distance_squared(spawn)
+        };
+        distance(*a).total_cmp(&distance(*b))
+    })
+}
+
+fn room_report_window_system(
+    mut contexts: EguiContexts,
+    state: Res<UiState>,
+    active_planet: Res<ActivePlanet>,
+    planet_query: Query<(&Name, &BevyPlanet, &PlanetSettings, &GlobalTransform)>,
+    mut export_event_writer: EventWriter<ExportEvent>,
+) {
+    if !state.show_room_report {
+        return;
+    }
+    let Some((name, planet, settings, transform)) = active_planet
+        .entity
+        .and_then(|entity| planet_query.get(entity).ok())
+    else {
+        return;
+    };
+    let Some(planet) = &planet.planet_data else {
+        return;
+    };
+
+    let space = PlanetSpace::from_planet(transform, planet);
+    let report = RoomReport::from_planet(planet, spawn_room(planet, &space, &settings.ui_state));
+
+    egui::Window::new("Rooms")
+        .default_width(420.)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.label(format!("rooms: {}", report.rows.len()));
+            ui.label(format!("mean size: {:.1} tiles", report.mean_size));
+            if let Some(largest) = report.largest {
+                let row = &report.rows[largest];
+                ui.label(format!("largest: room {} ({} tiles)", row.id, row.tiles));
+            }
+            ui.label(format!("mst components: {}", report.components));
+            if let Some(spawn) = report.spawn_room {
+                ui.label(format!("spawn room: {}", report.rows[spawn].id))
+                    .on_hover_text("the room nearest the point above the top of the planet");
+            }
+
+            if ui.button("export csv").clicked() {
+                export_event_writer.send(ExportEvent::RoomsCsv {
+                    name: format!("{}_rooms", name),
+                });
+            }
+
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("room_report").striped(true).show(ui, |ui| {
+                    for heading in ["id", "tiles", "bbox", "mst degree", "spawn mst hops"] {
+                        ui.strong(heading);
+                    }
+                    ui.end_row();
+
+                    for row in &report.rows {
+                        ui.label(row.id.to_string());
+                        ui.label(row.tiles.to_string());
+                        ui.label(format!(
+                            "{},{} - {},{}",
+                            row.bbox_min.x, row.bbox_min.y, row.bbox_max.x, row.bbox_max.y
+                        ));
+                        ui.label(row.mst_degree.to_string());
+                        ui.label(row.spawn_mst_hops.map_or("-".to_string(), |hops| hops.to_string()));
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+}
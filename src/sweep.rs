@@ -0,0 +1,450 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::{
+    bevy_planet::{
+        conversions::{bevy_image_to_imagebuffer, bitmap_display_to_bevy_image},
+        lib::build_planet,
+    },
+    planet_stats::PlanetStats,
+    ui_state::{BitmapDisplay, UiState},
+};
+
+/// A sweep file: the parameters to vary on top of a base preset, and how to
+/// lay out the contact sheet.
+///
+/// ```yaml
+/// layer: TileMapDebug
+/// parameters:
+///   - path: ca_options.iterations
+///     values: [4, 6, 8]
+///   - path: fractal_noises.0.frequency
+///     range: { start: 0.5, end: 1.5, steps: 3 }
+/// ```
+#[derive(Deserialize)]
+pub struct SweepSpec {
+    #[serde(default = "default_columns")]
+    pub columns: u32,
+    #[serde(default = "default_thumbnail_size")]
+    pub thumbnail_size: u32,
+    #[serde(default = "default_layer")]
+    pub layer: BitmapDisplay,
+    pub parameters: Vec<SweepParameter>,
+}
+
+/// A `UiState` field addressed by a dotted path, list entries by index.
+#[derive(Deserialize)]
+pub struct SweepParameter {
+    pub path: String,
+    #[serde(default)]
+    pub values: Vec<Value>,
+    pub range: Option<SweepRange>,
+}
+
+#[derive(Deserialize)]
+pub struct SweepRange {
+    pub start: f64,
+    pub end: f64,
+    pub steps: usize,
+}
+
+fn default_columns() -> u32 {
+    6
+}
+
+fn default_thumbnail_size() -> u32 {
+    160
+}
+
+fn default_layer() -> BitmapDisplay {
+    BitmapDisplay::PlanetProcessed
+}
+
+impl SweepParameter {
+    fn all_values(&self) -> Vec<Value> {
+        let mut values = self.values.clone();
+        if let Some(range) = &self.range {
+            let steps = range.steps.max(1);
+            for i in 0..steps {
+                let t = match steps {
+                    1 => 0.,
+                    _ => i as f64 / (steps - 1) as f64,
+                };
+                values.push(Value::from(range.start + (range.end - range.start) * t));
+            }
+        }
+        values
+    }
+}
+
+/// Builds every combination of the sweep parameters on top of `base`, then
+/// writes a labelled contact sheet and a csv of stats into `out`.
+pub fn run_sweep(base: &UiState, spec: &SweepSpec, out: &Path) -> Result<()> {
+    let axes: Vec<Vec<Value>> = spec
+        .parameters
+        .iter()
+        .map(SweepParameter::all_values)
+        .collect();
+    if axes.iter().any(|values| values.is_empty()) {
+        return Err(anyhow!("every sweep parameter needs at least one value"));
+    }
+    let combinations = cartesian_product(&axes);
+
+    let base = serde_yaml::to_value(base)?;
+    let size = spec.thumbnail_size;
+    let columns = spec.columns.max(1);
+    let rows = (combinations.len() as u32).div_ceil(columns);
+    let mut sheet: RgbaImage = ImageBuffer::from_pixel(columns * size, rows * size, Rgba([0, 0, 0, 255]));
+
+    let names = short_names(&spec.parameters);
+    let mut csv = String::from("index,");
+    for parameter in &spec.parameters {
+        csv.push_str(&parameter.path);
+        csv.push(',');
+    }
+    csv.push_str(PlanetStats::CSV_HEADER);
+    csv.push_str(",error\n");
+
+    for (index, combination) in combinations.iter().enumerate() {
+        let mut value = base.clone();
+        for (parameter, v) in spec.parameters.iter().zip(combination) {
+            set_path(&mut value, &parameter.path, v.clone())?;
+        }
+        let state: UiState = serde_yaml::from_value(value)?;
+
+        csv.push_str(&format!("{},", index));
+        for v in combination {
+            csv.push_str(&serde_yaml::to_string(v)?.trim().replace(',', ";"));
+            csv.push(',');
+        }
+
+        let x = (index as u32 % columns) * size;
+        let y = (index as u32 / columns) * size;
+
+        match build_planet(&state) {
+            Ok(planet) => {
+                csv.push_str(&PlanetStats::from_planet(&planet).csv_row());
+                csv.push_str(",\n");
+
+                if let Some(image) = bitmap_display_to_bevy_image(&planet, &spec.layer, &state.map_style)
+                    .as_ref()
+                    .and_then(bevy_image_to_imagebuffer)
+                {
+                    let thumbnail = imageops::resize(&image, size, size, imageops::FilterType::Nearest);
+                    for (tx, ty, pixel) in thumbnail.enumerate_pixels() {
+                        // some layers are nearly transparent, the sheet is opaque
+                        let [r, g, b, _] = pixel.0;
+                        sheet.put_pixel(x + tx, y + ty, Rgba([r, g, b, 255]));
+                    }
+                }
+            }
+            Err(err) => {
+                csv.push_str(&",".repeat(PlanetStats::CSV_HEADER.split(',').count()));
+                csv.push_str(&err.replace([',', '\n'], " "));
+                csv.push('\n');
+            }
+        }
+
+        // the index and the swept values, one per line, clipped to the cell
+        let mut label_y = y + 2;
+        let label_width = size.saturating_sub(4);
+        draw_label(&mut sheet, x + 2, label_y, label_width, &index.to_string(), 2);
+        label_y += LABEL_LINE_HEIGHT * 2;
+        for (name, v) in names.iter().zip(combination) {
+            let text = format!("{}={}", name, format_value(v));
+            draw_label(&mut sheet, x + 2, label_y, label_width, &text, 1);
+            label_y += LABEL_LINE_HEIGHT;
+        }
+        println!("built {}/{}", index + 1, combinations.len());
+    }
+
+    fs::create_dir_all(out)?;
+    sheet.save(out.join("contact_sheet.png"))?;
+    fs::write(out.join("stats.csv"), csv)?;
+    println!("wrote {}", out.display());
+
+    Ok(())
+}
+
+/// The shortest trailing part of each parameter path that still tells it apart
+/// from the others, `fractal_noises.1.frequency` becomes `1.frequency` next to
+/// `fractal_noises.0.frequency` and just `frequency` on its own.
+fn short_names(parameters: &[SweepParameter]) -> Vec<String> {
+    let tail = |path: &str, segments: usize| {
+        let parts: Vec<&str> = path.split('.').collect();
+        parts[parts.len().saturating_sub(segments)..].join(".")
+    };
+    parameters
+        .iter()
+        .map(|parameter| {
+            let path = &parameter.path;
+            let depth = path.split('.').count();
+            (1..=depth)
+                .map(|segments| tail(path, segments))
+                .find(|name| {
+                    parameters
+                        .iter()
+                        .filter(|other| other.path != *path)
+                        .all(|other| tail(&other.path, name.split('.').count()) != *name)
+                })
+                .unwrap_or_else(|| path.clone())
+        })
+        .collect()
+}
+
+/// A value as it goes on the contact sheet, floats cut to 3 decimals.
+fn format_value(v: &Value) -> String {
+    match v {
+        Value::Number(n) if n.is_f64() => {
+            let text = format!("{:.3}", n.as_f64().unwrap_or_default());
+            text.trim_end_matches('0').trim_end_matches('.').to_string()
+        }
+        Value::String(text) => text.clone(),
+        _ => serde_yaml::to_string(v)
+            .map(|text| text.trim().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn cartesian_product(axes: &[Vec<Value>]) -> Vec<Vec<Value>> {
+    axes.iter().fold(vec![vec![]], |combinations, values| {
+        combinations
+            .iter()
+            .flat_map(|combination| {
+                values.iter().map(move |v| {
+                    let mut combination = combination.clone();
+                    combination.push(v.clone());
+                    combination
+                })
+            })
+            .collect()
+    })
+}
+
+/// Sets the field at a dotted path such as `fractal_noises.0.frequency`.
+/// Floats written over integer fields are rounded, so ranges work for both.
+fn set_path(value: &mut Value, path: &str, new: Value) -> Result<()> {
+    let mut current = value;
+    for segment in path.split('.') {
+        current = match current {
+            Value::Sequence(sequence) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| sequence.get_mut(i)),
+            Value::Mapping(mapping) => mapping.get_mut(segment),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("no parameter {} in the preset", path))?;
+    }
+
+    *current = match (&*current, new.as_f64()) {
+        (Value::Number(n), Some(f)) if n.is_u64() || n.is_i64() => Value::from(f.round() as i64),
+        _ => new,
+    };
+    Ok(())
+}
+
+// 3x5 pixel glyphs, one row per byte, high bit on the left. Letters are
+// drawn upper case
+fn glyph(c: char) -> Option<[u8; 5]> {
+    Some(match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        ' ' => [0; 5],
+        _ => return None,
+    })
+}
+
+// rows of one label line at scale 1, glyph plus padding above and below
+const LABEL_LINE_HEIGHT: u32 = 7;
+
+/// Draws text in white on a black box, `scale` pixels per glyph pixel. Text
+/// that doesn't fit in `max_width` is cut off, unknown characters are blank.
+fn draw_label(image: &mut RgbaImage, x: u32, y: u32, max_width: u32, text: &str, scale: u32) {
+    let glyph_width = 4 * scale;
+    let fits = (max_width.saturating_sub(scale) / glyph_width) as usize;
+    let text: Vec<char> = text.chars().take(fits).collect();
+    let width = text.len() as u32 * glyph_width + scale;
+    let height = LABEL_LINE_HEIGHT * scale;
+
+    let mut put = |px: u32, py: u32, color: [u8; 4]| {
+        if px < image.width() && py < image.height() {
+            image.put_pixel(px, py, Rgba(color));
+        }
+    };
+
+    for py in 0..height {
+        for px in 0..width {
+            put(x + px, y + py, [0, 0, 0, 255]);
+        }
+    }
+
+    for (i, c) in text.iter().enumerate() {
+        let Some(glyph) = glyph(*c) else {
+            continue;
+        };
+        let gx = x + scale + i as u32 * glyph_width;
+        let gy = y + scale;
+        for (row, bits) in glyph.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        put(
+                            gx + column * scale + sx,
+                            gy + row as u32 * scale + sy,
+                            [255, 255, 255, 255],
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameter(path: &str, values: Vec<Value>, range: Option<SweepRange>) -> SweepParameter {
+        SweepParameter {
+            path: path.to_string(),
+            values,
+            range,
+        }
+    }
+
+    #[test]
+    fn set_path_walks_mappings_and_lists() {
+        let mut value: Value =
+            serde_yaml::from_str("a:\n  b: 1.5\n  list:\n    - x: 1\n    - x: 2\n").unwrap();
+
+        set_path(&mut value, "a.b", Value::from(2.5)).unwrap();
+        set_path(&mut value, "a.list.1.x", Value::from(7)).unwrap();
+
+        assert_eq!(value["a"]["b"], Value::from(2.5));
+        assert_eq!(value["a"]["list"][0]["x"], Value::from(1));
+        assert_eq!(value["a"]["list"][1]["x"], Value::from(7));
+    }
+
+    #[test]
+    fn set_path_rounds_floats_into_integer_fields() {
+        let mut value: Value = serde_yaml::from_str("iterations: 4").unwrap();
+        set_path(&mut value, "iterations", Value::from(5.6)).unwrap();
+        assert_eq!(value["iterations"], Value::from(6));
+    }
+
+    #[test]
+    fn set_path_rejects_missing_fields() {
+        let mut value: Value = serde_yaml::from_str("a:\n  list: [1, 2]\n").unwrap();
+        assert!(set_path(&mut value, "a.missing", Value::from(1)).is_err());
+        assert!(set_path(&mut value, "a.list.5", Value::from(1)).is_err());
+        assert!(set_path(&mut value, "a.list.x", Value::from(1)).is_err());
+    }
+
+    /// The paths in the `SweepSpec` example must keep matching `UiState`, a
+    /// renamed field would otherwise only show up when a sweep is run.
+    #[test]
+    fn documented_paths_exist_in_the_default_state() {
+        let mut value = serde_yaml::to_value(UiState::default()).unwrap();
+
+        set_path(&mut value, "ca_options.iterations", Value::from(6)).unwrap();
+        set_path(&mut value, "fractal_noises.0.frequency", Value::from(1.25)).unwrap();
+
+        let state: UiState = serde_yaml::from_value(value).unwrap();
+        let state = serde_yaml::to_value(&state).unwrap();
+        assert_eq!(state["ca_options"]["iterations"], Value::from(6));
+        assert_eq!(state["fractal_noises"][0]["frequency"], Value::from(1.25));
+    }
+
+    #[test]
+    fn range_expands_to_evenly_spaced_values() {
+        let range = SweepRange {
+            start: 0.5,
+            end: 1.5,
+            steps: 3,
+        };
+        let values = parameter("a", vec![Value::from(9)], Some(range)).all_values();
+        assert_eq!(
+            values,
+            vec![Value::from(9), Value::from(0.5), Value::from(1.0), Value::from(1.5)]
+        );
+    }
+
+    #[test]
+    fn combinations_cover_every_pairing_in_order() {
+        let axes = vec![
+            vec![Value::from(1), Value::from(2)],
+            vec![Value::from("a"), Value::from("b"), Value::from("c")],
+        ];
+        let combinations = cartesian_product(&axes);
+
+        assert_eq!(combinations.len(), 6);
+        assert_eq!(combinations[0], vec![Value::from(1), Value::from("a")]);
+        assert_eq!(combinations[2], vec![Value::from(1), Value::from("c")]);
+        assert_eq!(combinations[3], vec![Value::from(2), Value::from("a")]);
+        assert_eq!(combinations[5], vec![Value::from(2), Value::from("c")]);
+    }
+
+    #[test]
+    fn short_names_keep_enough_of_the_path() {
+        let parameters = vec![
+            parameter("fractal_noises.0.frequency", vec![], None),
+            parameter("fractal_noises.1.frequency", vec![], None),
+            parameter("ca_options.iterations", vec![], None),
+        ];
+        assert_eq!(
+            short_names(&parameters),
+            vec!["0.frequency", "1.frequency", "iterations"]
+        );
+    }
+}
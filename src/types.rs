@@ -6,8 +6,8 @@ use crate::ui_state::UiState;
 impl From<UiState> for PlanetOptions {
     fn from(ui_state: UiState) -> Self {
         Self {
-            seed: 0,
-            min_room_size: 20,
+            seed: ui_state.seed,
+            min_room_size: ui_state.min_room_size,
             radius: ui_state.radius,
             resolution: ui_state.resolution,
             ca_options: ui_state.ca_options,
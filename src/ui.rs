@@ -2,16 +2,17 @@
 use bevy::{
     app::{App, Plugin, Startup, Update}, core_pipeline::core_3d::Camera3d, ecs::{
         event::{Event, EventReader, EventWriter}, query::With, system::{Query, Res, ResMut, Resource}
-    }, input::{mouse::{MouseButton, MouseButtonInput}, ButtonInput}, math::primitives::Plane3d, render::camera::Camera, transform::components::{GlobalTransform, Transform}, window::CursorMoved
+    }, input::{mouse::{MouseButton, MouseButtonInput}, ButtonInput}, math::primitives::Plane3d, render::camera::Camera, transform::components::{GlobalTransform, Transform}, window::{CursorMoved, PrimaryWindow, Window}
 };
 use bevy_egui::{
     egui, EguiContext, EguiContexts, EguiPlugin
 };
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use glam::{Vec2, Vec3};
+use rand::Rng;
 use strum::IntoEnumIterator; // Import necessary traits
 
-use crate::{bevy_planet::lib::PlanetRootTag, planet_gizmos::GizmoOptions, player::{PlayerEvent, PlayerEventType}, ui_state::{self, BitmapDisplay, CameraMode, UiState}};
+use crate::{export::{svg::SvgOptions, ExportEvent}, bevy_planet::{colormap::{Colormap, Normalization}, history::EditHistory, layers::BlendMode, lib::{ActivePlanet, ContourStats, PlanetGenerationTask, PlanetListEvent, PlanetRootTag, MapLegend, PlanetFileEvent, PlanetSettings, TerrainHistoryEvent}}, planet_gizmos::GizmoOptions, player::{PlayerEvent, PlayerEventType}, ui_state::{self, BitmapDisplay, BrushShape, CameraMode, ColliderContours, CollisionMode, UiState}};
 
 
 pub struct PlanetUiPlugin;
@@ -19,6 +20,9 @@ pub struct PlanetUiPlugin;
 impl Plugin for PlanetUiPlugin {
     fn build(&self, app: &mut App) {
         let ui_state: UiState = UiState::load().unwrap_or_default();
+        let seed_history = SeedHistory {
+            seeds: vec![ui_state.seed],
+        };
 
         app.add_plugins(WorldInspectorPlugin::new())
             .insert_resource(ui_state)
@@ -28,9 +32,15 @@ impl Plugin for PlanetUiPlugin {
             .add_systems(Update, ui_system)
             .add_event::<RegeneratePlanetEvent>()
             .add_event::<GeneralUpdateEvent>()
+            .add_event::<ContoursChangedEvent>()
             .add_event::<MouseClickWorldEvent>()
             .init_resource::<OccupiedScreenSpace>()
-            .add_systems(Update, mouse_click_world);
+            .init_resource::<PlanetListUiState>()
+            .init_resource::<ExportUiState>()
+            .init_resource::<CursorWorldPosition>()
+            .insert_resource(seed_history)
+            .add_systems(Update, mouse_click_world)
+            .add_systems(Update, cursor_world_position_system);
     }
 }
 
@@ -42,6 +52,35 @@ pub struct OccupiedScreenSpace {
     // bottom: f32,
 }
 
+#[derive(Default, Resource)]
+pub struct ExportUiState {
+    pub name: String,
+    pub svg_options: SvgOptions,
+}
+
+#[derive(Default, Resource)]
+pub struct PlanetListUiState {
+    pub new_planet_position: Vec2,
+    pub planet_file_name: String,
+}
+
+const SEED_HISTORY_LENGTH: usize = 10;
+
+/// Seeds used during this session, most recent last. Not persisted.
+#[derive(Default, Resource)]
+pub struct SeedHistory {
+    pub seeds: Vec<u32>,
+}
+
+impl SeedHistory {
+    pub fn push(&mut self, seed: u32) {
+        self.seeds.retain(|s| *s != seed);
+        self.seeds.push(seed);
+        if self.seeds.len() > SEED_HISTORY_LENGTH {
+            self.seeds.remove(0);
+        }
+    }
+}
 
 #[derive(Event, Debug)]
 pub struct RegeneratePlanetEvent {
@@ -66,6 +105,10 @@ pub struct GeneralUpdateEvent {
     // pub ui_state: UiState,
 }
 
+/// Contour simplification settings changed, no re-march needed.
+#[derive(Event, Debug)]
+pub struct ContoursChangedEvent;
+
 fn init_planet_system(state: ResMut<UiState>, mut event_writer: EventWriter<RegeneratePlanetEvent>) {
     event_writer.send(RegeneratePlanetEvent {
         ui_state: state.clone(),
@@ -82,37 +125,53 @@ pub struct MouseClickWorldEvent {
 
 
 
+/// Where the cursor meets the z = 0 plane, `None` while it's over the ui or off the window.
+#[derive(Default, Resource)]
+pub struct CursorWorldPosition {
+    pub pos: Option<Vec3>,
+}
+
+/// Screen position to the point on the z = 0 plane under it. Every planet lies
+/// in that plane, the planet systems work out which one was hit.
+fn cursor_to_world(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    occupied_screen_space: &OccupiedScreenSpace,
+    position: Vec2,
+) -> Option<Vec3> {
+    let plane_origin = Vec3::ZERO;
+    let plane = Plane3d::new(Vec3::new(0., 0., -1.));
+
+    // Ask Bevy to give us a ray pointing from the viewport (screen) into the world
+    let offset_vec = Vec2::new(position.x - occupied_screen_space.left, position.y);
+    let ray = camera.viewport_to_world(camera_transform, offset_vec)?;
+
+    // do a ray-plane intersection test, giving us the distance to the ground
+    let distance = ray.intersect_plane(plane_origin, plane)?;
+
+    // use the distance to compute the actual point on the ground in world-space
+    Some(ray.get_point(distance))
+}
+
 fn mouse_click_world(
     clicked: Res<ButtonInput<MouseButton>>,
     mut cursor_position: EventReader<CursorMoved>, 
     q_camera: Query<(&Camera, &GlobalTransform)>,
-    q_plane: Query<&GlobalTransform, With<PlanetRootTag>>,
     occupied_screen_space: ResMut<OccupiedScreenSpace>,
     mut click_event_writer: EventWriter<MouseClickWorldEvent>,
 ) {
     let (camera, camera_transform) = q_camera.single();
-    let ground_transform = q_plane.single();
 
 
     for event in cursor_position.read() {
-        
-        let plane_origin = ground_transform.translation();
-        let plane = Plane3d::new(Vec3::new(0., 0., -1.));
-    
-        // Ask Bevy to give us a ray pointing from the viewport (screen) into the world
-        let offset_vec = Vec2::new(event.position.x - occupied_screen_space.left, event.position.y);
-        let Some(ray) = camera.viewport_to_world(camera_transform, offset_vec) else {
-            return;
-        };
-    
-        // do a ray-plane intersection test, giving us the distance to the ground
-        let Some(distance) = ray.intersect_plane(plane_origin, plane) else {
-
+        let Some(global_cursor) = cursor_to_world(
+            camera,
+            camera_transform,
+            &occupied_screen_space,
+            event.position,
+        ) else {
             return;
         };
-
-        // use the distance to compute the actual point on the ground in world-space
-        let global_cursor = ray.get_point(distance);
         
         if clicked.pressed(MouseButton::Left) {
             click_event_writer.send(
@@ -129,9 +188,37 @@ fn mouse_click_world(
                     button: MouseButton::Right
             });
         }
+
+        if clicked.pressed(MouseButton::Middle) {
+            click_event_writer.send(
+                MouseClickWorldEvent {
+                    pos: global_cursor,
+                    button: MouseButton::Middle
+            });
+        }
     }
 }
 
+/// Tracks the cursor every frame, so hovering works without moving the mouse
+/// while the camera follows the player.
+fn cursor_world_position_system(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Camera, &GlobalTransform)>,
+    occupied_screen_space: Res<OccupiedScreenSpace>,
+    mut cursor: ResMut<CursorWorldPosition>,
+) {
+    let (camera, camera_transform) = q_camera.single();
+
+    cursor.pos = windows
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .filter(|position| position.x >= occupied_screen_space.left)
+        .and_then(|position| {
+            cursor_to_world(camera, camera_transform, &occupied_screen_space, position)
+        });
+}
+
 
 fn ui_system(
     mut contexts: EguiContexts,
@@ -140,13 +227,31 @@ fn ui_system(
     mut player_event_writer: EventWriter<crate::player::PlayerEvent>,
     mut mesh_event_writer: EventWriter<ModifyMeshEvent>,
     mut general_update_event_writer: EventWriter<GeneralUpdateEvent>,
+    mut contours_changed_event_writer: EventWriter<ContoursChangedEvent>,
     mut occupied_screen_space: ResMut<OccupiedScreenSpace>,
+    generation_query: Query<(), With<PlanetGenerationTask>>,
+    mut seed_history: ResMut<SeedHistory>,
+    planet_query: Query<
+        (Entity, &Name, &PlanetSettings, &ContourStats, &EditHistory),
+        With<PlanetRootTag>,
+    >,
+    mut active_planet: ResMut<ActivePlanet>,
+    mut planet_list_event_writer: EventWriter<PlanetListEvent>,
+    mut planet_list_ui_state: ResMut<PlanetListUiState>,
+    (mut history_event_writer, mut planet_file_event_writer, mut export_event_writer, mut export_ui_state, legend): (
+        EventWriter<TerrainHistoryEvent>,
+        EventWriter<PlanetFileEvent>,
+        EventWriter<ExportEvent>,
+        ResMut<ExportUiState>,
+        Res<MapLegend>,
+    ),
 ) {
 
     let mut planet_gen_settings_changed = false;
     let mut player_settings_changed = false;
     let mut camera_settings_changed = false;
     let mut general_changed = false;
+    let mut contours_changed = false;
     
 
     let smaller_space = 10.;
@@ -162,6 +267,64 @@ fn ui_system(
             let heading_style = egui::TextStyle::Heading;
             let c = 180;
 
+            if !generation_query.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("generating…");
+                });
+            }
+
+            ui.label(
+                egui::RichText::new("Planets")
+                    .text_style(heading_style.clone())
+                    .color(egui::Color32::from_rgb(c, c, c)),
+            );
+            ui.add_space(larger_space);
+
+            let planet_count = planet_query.iter().count();
+            for (entity, name, settings, _, _) in planet_query.iter() {
+                ui.horizontal(|ui| {
+                    let is_active = active_planet.entity == Some(entity);
+                    if ui.selectable_label(is_active, name.as_str()).clicked() && !is_active {
+                        active_planet.entity = Some(entity);
+                        *state = state.with_planet_parameters(&settings.ui_state);
+                        general_changed = true;
+                    }
+                    if planet_count > 1 && ui.small_button("remove").clicked() {
+                        planet_list_event_writer.send(PlanetListEvent::Despawn { planet: entity });
+                    }
+                });
+            }
+
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut planet_list_ui_state.new_planet_position.x));
+                ui.label("x");
+                ui.add(egui::DragValue::new(&mut planet_list_ui_state.new_planet_position.y));
+                ui.label("y");
+                if ui.button("add planet").clicked() {
+                    planet_list_event_writer.send(PlanetListEvent::Spawn {
+                        ui_state: state.clone(),
+                        position: planet_list_ui_state.new_planet_position,
+                    });
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut planet_list_ui_state.planet_file_name)
+                        .hint_text("planet file")
+                        .desired_width(150.),
+                );
+                let name = planet_list_ui_state.planet_file_name.trim().to_string();
+                if ui.add_enabled(!name.is_empty(), egui::Button::new("save")).clicked() {
+                    planet_file_event_writer.send(PlanetFileEvent::Save { name: name.clone() });
+                }
+                if ui.add_enabled(!name.is_empty(), egui::Button::new("load")).clicked() {
+                    planet_file_event_writer.send(PlanetFileEvent::Load { name });
+                }
+            });
+
+            ui.add_space(smaller_space);
             ui.label(
                 egui::RichText::new("Initial")
                     .text_style(heading_style)
@@ -184,6 +347,35 @@ fn ui_system(
                 .add(egui::Slider::new(&mut state.resolution, 10..=1000).text("resolution"))
                 .changed();
 
+            ui.horizontal(|ui| {
+                let seed_response = ui.add(egui::DragValue::new(&mut state.seed));
+                planet_gen_settings_changed |= seed_response.changed();
+                // only record the value the user settles on, not every drag step
+                if seed_response.drag_released() || seed_response.lost_focus() {
+                    seed_history.push(state.seed);
+                }
+                ui.label("seed");
+                if ui.button("randomize").clicked() {
+                    state.seed = rand::thread_rng().gen();
+                    seed_history.push(state.seed);
+                    planet_gen_settings_changed = true;
+                }
+            });
+
+            ui.horizontal_wrapped(|ui| {
+                ui.label("history:");
+                for seed in seed_history.seeds.clone().iter().rev() {
+                    if ui
+                        .selectable_label(*seed == state.seed, seed.to_string())
+                        .clicked()
+                        && *seed != state.seed
+                    {
+                        state.seed = *seed;
+                        planet_gen_settings_changed = true;
+                    }
+                }
+            });
+
             ui.add_space(smaller_space);
             let heading_style = egui::TextStyle::Heading;
             ui.label(
@@ -468,6 +660,82 @@ fn ui_system(
                     .changed();
             }
 
+            ui.horizontal(|ui| {
+                ui.label("colormap");
+                for variant in Colormap::iter() {
+                    general_changed |= ui
+                        .radio_value(
+                            &mut state.map_style.colormap,
+                            variant,
+                            format!("{:?}", variant),
+                        )
+                        .changed();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("normalization");
+                for variant in Normalization::iter() {
+                    general_changed |= ui
+                        .radio_value(
+                            &mut state.map_style.normalization,
+                            variant,
+                            format!("{:?}", variant),
+                        )
+                        .changed();
+                }
+            });
+
+            if let Some((min, max)) = legend.range {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{:.3}", min));
+                    let (rect, _) = ui.allocate_exact_size(
+                        egui::vec2(200., 12.),
+                        egui::Sense::hover(),
+                    );
+                    let steps = 64;
+                    for i in 0..steps {
+                        let [r, g, b, _] = legend.colormap.sample(i as f32 / (steps - 1) as f32);
+                        let x0 = rect.left() + rect.width() * i as f32 / steps as f32;
+                        let x1 = rect.left() + rect.width() * (i + 1) as f32 / steps as f32;
+                        ui.painter().rect_filled(
+                            egui::Rect::from_x_y_ranges(x0..=x1, rect.y_range()),
+                            0.,
+                            egui::Color32::from_rgb(r, g, b),
+                        );
+                    }
+                    ui.label(format!("{:.3}", max));
+                });
+            }
+
+            ui.collapsing("Layers", |ui| {
+                for (i, layer) in state.texture_layers.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        general_changed |= ui
+                            .checkbox(&mut layer.enabled, format!("{:?}", layer.display))
+                            .changed();
+                        general_changed |= ui
+                            .add(
+                                egui::Slider::new(&mut layer.opacity, 0.0..=1.)
+                                    .text("opacity"),
+                            )
+                            .changed();
+                        egui::ComboBox::from_id_source(("texture_layer_blend", i))
+                            .selected_text(format!("{:?}", layer.blend))
+                            .show_ui(ui, |ui| {
+                                for variant in BlendMode::iter() {
+                                    general_changed |= ui
+                                        .selectable_value(
+                                            &mut layer.blend,
+                                            variant,
+                                            format!("{:?}", variant),
+                                        )
+                                        .changed();
+                                }
+                            });
+                    });
+                }
+            });
+
             ui.add_space(smaller_space);
 
             general_changed |= ui
@@ -480,6 +748,17 @@ fn ui_system(
 
                 general_changed |= ui.checkbox(&mut state.show_debug, "Show Debug").changed();
 
+                general_changed |= ui
+                    .checkbox(&mut state.show_inspector, "Show Inspector")
+                    .changed();
+
+                general_changed |= ui
+                    .checkbox(&mut state.show_room_report, "Show Room Report")
+                    .changed();
+
+                // the fill is built with the outline mesh
+                contours_changed |= ui.checkbox(&mut state.show_fill, "Show Fill").changed();
+
             ui.add_space(10.0);
             let heading_style = egui::TextStyle::Heading;
             let c = 180;
@@ -495,6 +774,39 @@ fn ui_system(
             general_changed |= ui
                 .add(egui::Slider::new(&mut state.brush_size, 0.0..=1.).text("brush"))
                 .changed();
+            ui.horizontal(|ui| {
+                for variant in BrushShape::iter() {
+                    general_changed |= ui
+                        .radio_value(
+                            &mut state.brush_shape,
+                            variant.clone(),
+                            format!("{:?}", variant),
+                        )
+                        .changed();
+                }
+            });
+            general_changed |= ui
+                .add(egui::Slider::new(&mut state.brush_softness, 0.0..=1.).text("brush softness"))
+                .changed();
+            general_changed |= ui
+                .add(egui::Slider::new(&mut state.brush_strength, 0.0..=1.).text("brush strength"))
+                .changed();
+
+            let history = active_planet
+                .entity
+                .and_then(|entity| planet_query.get(entity).ok())
+                .map(|(_, _, _, _, history)| history);
+            ui.horizontal(|ui| {
+                let can_undo = history.is_some_and(|history| history.can_undo());
+                let can_redo = history.is_some_and(|history| history.can_redo());
+                if ui.add_enabled(can_undo, egui::Button::new("undo")).clicked() {
+                    history_event_writer.send(TerrainHistoryEvent::Undo);
+                }
+                if ui.add_enabled(can_redo, egui::Button::new("redo")).clicked() {
+                    history_event_writer.send(TerrainHistoryEvent::Redo);
+                }
+                ui.label("ctrl+z / ctrl+shift+z");
+            });
 
             ui.add_space(larger_space);
 
@@ -512,13 +824,103 @@ fn ui_system(
                 general_changed |= ui
                     .checkbox(&mut state.gizmo_options.draw_mst, "MST")
                     .changed();
+                general_changed |= ui
+                    .checkbox(&mut state.gizmo_options.draw_path, "Path")
+                    .changed();
+                ui.label("middle click to set the path target");
+            });
+
+            ui.collapsing("Contours", |ui| {
+                contours_changed |= ui
+                    .add(
+                        egui::Slider::new(&mut state.render_tolerance, 0.0..=5.)
+                            .text("render tolerance"),
+                    )
+                    .changed();
+                contours_changed |= ui
+                    .add(
+                        egui::Slider::new(&mut state.collider_tolerance, 0.0..=5.)
+                            .text("collider tolerance"),
+                    )
+                    .changed();
+
+                contours_changed |= ui
+                    .add(
+                        egui::Slider::new(&mut state.smoothing_iterations, 0..=5)
+                            .text("smoothing iterations"),
+                    )
+                    .changed();
+                ui.horizontal(|ui| {
+                    ui.label("colliders from");
+                    for variant in ColliderContours::iter() {
+                        contours_changed |= ui
+                            .radio_value(
+                                &mut state.collider_contours,
+                                variant.clone(),
+                                format!("{:?}", variant),
+                            )
+                            .changed();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("collision");
+                    for variant in CollisionMode::iter() {
+                        contours_changed |= ui
+                            .radio_value(
+                                &mut state.collision_mode,
+                                variant.clone(),
+                                format!("{:?}", variant),
+                            )
+                            .changed();
+                    }
+                });
+
+                let stats = active_planet
+                    .entity
+                    .and_then(|entity| planet_query.get(entity).ok())
+                    .map(|(_, _, _, stats, _)| stats);
+                if let Some(stats) = stats {
+                    ui.label(format!("raw vertices: {}", stats.raw_vertices));
+                    ui.label(format!("render vertices: {}", stats.render_vertices));
+                    ui.label(format!("collider vertices: {}", stats.collider_vertices));
+                }
             });
 
             planet_gen_settings_changed |= ui.checkbox(&mut state.rooms, "Rooms").changed();
             planet_gen_settings_changed |= ui.checkbox(&mut state.tunnels, "Tunnels").changed();
+            planet_gen_settings_changed |= ui
+                .add(egui::Slider::new(&mut state.min_room_size, 0..=500).text("min room size"))
+                .changed();
 
 
 
+            ui.collapsing("Export", |ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut export_ui_state.name)
+                        .hint_text("file name"),
+                );
+                let mut world_units = export_ui_state.svg_options.world_scale.is_some();
+                if ui.checkbox(&mut world_units, "world units").changed() {
+                    // the exporter fills in the planet's own scale
+                    export_ui_state.svg_options.world_scale = world_units.then_some(1.);
+                }
+                ui.checkbox(&mut export_ui_state.svg_options.room_centers, "room centers");
+                ui.checkbox(&mut export_ui_state.svg_options.mst, "MST");
+                ui.checkbox(&mut export_ui_state.svg_options.triangulation, "triangulation");
+
+                let name = export_ui_state.name.trim().to_string();
+                if ui.add_enabled(!name.is_empty(), egui::Button::new("export svg")).clicked() {
+                    export_event_writer.send(ExportEvent::Svg {
+                        name: name.clone(),
+                        options: export_ui_state.svg_options.clone(),
+                    });
+                }
+                if ui.add_enabled(!name.is_empty(), egui::Button::new("export tiled")).clicked() {
+                    export_event_writer.send(ExportEvent::Tiled { name });
+                }
+            });
+
             ui.collapsing("Player", |ui| {
                 player_settings_changed |= ui
                     .add(egui::Slider::new(&mut state.player_move_force, 0.0..=60.).text("move force"))
@@ -585,6 +987,11 @@ fn ui_system(
                     ui_state: state.clone()});
             }
 
+            if contours_changed {
+                state.save().ok();
+                contours_changed_event_writer.send(ContoursChangedEvent);
+            }
+
             if general_changed{
                 state.save().ok();
                 general_update_event_writer.send(GeneralUpdateEvent{
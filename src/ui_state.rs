@@ -1,5 +1,6 @@
 use std::env;
 use std::fs;
+use std::path::Path;
 
 use anyhow::Result;
 use bevy::ecs::system::Resource;
@@ -11,6 +12,8 @@ use serde::{Deserialize, Serialize};
 use serde_yaml;
 use strum_macros::EnumIter;
 
+use crate::bevy_planet::colormap::MapStyle;
+use crate::bevy_planet::layers::{default_texture_layers, TextureLayer};
 use crate::planet_gizmos::GizmoOptions;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
@@ -33,6 +36,31 @@ pub enum CameraMode {
     Player
 }
 
+/// Which contours the terrain colliders are built from.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum ColliderContours {
+    #[default]
+    Raw,
+    Smoothed,
+}
+
+/// Polyline colliders are hollow, solid ones are triangulated from the filled terrain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum CollisionMode {
+    #[default]
+    Polyline,
+    Solid,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    Square,
+    Line,
+    Lasso,
+}
+
 #[derive(Resource, Debug, Clone, Serialize, Deserialize)]
 pub struct UiState {
     pub changed: bool,
@@ -61,6 +89,49 @@ pub struct UiState {
     pub camera_mode: CameraMode,
     pub game_camera_zoom: f32,
     pub brush_size: f32,
+    #[serde(default)]
+    pub brush_shape: BrushShape,
+    /// soft edge width as a fraction of the brush radius
+    #[serde(default)]
+    pub brush_softness: f32,
+    #[serde(default = "default_brush_strength")]
+    pub brush_strength: f32,
+    #[serde(default)]
+    pub seed: u32,
+    #[serde(default = "default_min_room_size")]
+    pub min_room_size: usize,
+    /// Douglas-Peucker tolerances in image pixels, 0 keeps every vertex
+    #[serde(default)]
+    pub render_tolerance: f32,
+    #[serde(default)]
+    pub collider_tolerance: f32,
+    /// Chaikin passes over the rendered contours
+    #[serde(default)]
+    pub smoothing_iterations: u32,
+    #[serde(default)]
+    pub collider_contours: ColliderContours,
+    #[serde(default)]
+    pub show_fill: bool,
+    #[serde(default)]
+    pub collision_mode: CollisionMode,
+    #[serde(default)]
+    pub map_style: MapStyle,
+    /// overlays blended over `bitmap_dislpay` on the texture plane
+    #[serde(default = "default_texture_layers")]
+    pub texture_layers: Vec<TextureLayer>,
+    /// shows what's under the cursor
+    #[serde(default)]
+    pub show_inspector: bool,
+    #[serde(default)]
+    pub show_room_report: bool,
+}
+
+fn default_min_room_size() -> usize {
+    20
+}
+
+fn default_brush_strength() -> f32 {
+    1.0
 }
 
 impl Default for UiState {
@@ -96,11 +167,50 @@ impl Default for UiState {
             camera_mode: CameraMode::BothOverview,
             game_camera_zoom: 30.0,
             brush_size: 0.5,
+            brush_shape: BrushShape::Circle,
+            brush_softness: 0.0,
+            brush_strength: default_brush_strength(),
+            seed: 0,
+            min_room_size: default_min_room_size(),
+            render_tolerance: 0.0,
+            collider_tolerance: 0.0,
+            smoothing_iterations: 0,
+            collider_contours: ColliderContours::Raw,
+            show_fill: false,
+            collision_mode: CollisionMode::Polyline,
+            map_style: MapStyle::default(),
+            texture_layers: default_texture_layers(),
+            show_inspector: false,
+            show_room_report: false,
         }
     }
 }
 
 impl UiState {
+    /// Copies the per-planet generation parameters from `planet`, keeping the
+    /// session-wide display, player and camera settings of `self`.
+    pub fn with_planet_parameters(&self, planet: &UiState) -> UiState {
+        UiState {
+            fractal_noises: planet.fractal_noises.clone(),
+            noise_mask_options: planet.noise_mask_options.clone(),
+            global_noise_options: planet.global_noise_options.clone(),
+            radius: planet.radius,
+            resolution: planet.resolution,
+            ca_options: planet.ca_options.clone(),
+            blur: planet.blur,
+            scale: planet.scale,
+            crust_thickness: planet.crust_thickness,
+            displacement_scale: planet.displacement_scale,
+            displacement_frequency: planet.displacement_frequency,
+            invert_ca: planet.invert_ca,
+            rooms: planet.rooms,
+            tunnels: planet.tunnels,
+            seed: planet.seed,
+            min_room_size: planet.min_room_size,
+            ..self.clone()
+        }
+    }
+
     pub fn save(&self) -> Result<()> {
         let yaml = serde_yaml::to_string(self)?;
         let file_path = env::current_dir()?.join("save/save.yaml");
@@ -114,6 +224,11 @@ impl UiState {
 
     pub fn load() -> Result<UiState> {
         let file_path = env::current_dir()?.join("save/save.yaml");
+        UiState::load_from(&file_path)
+    }
+
+    /// Loads a preset, any file with the schema of save/save.yaml.
+    pub fn load_from(file_path: &Path) -> Result<UiState> {
         let contents = fs::read_to_string(file_path)?;
         let v = serde_yaml::from_str(&contents)?;
         Ok(v)